@@ -1,246 +1,1220 @@
 extern crate proc_macro;
 use proc_macro::TokenStream;
-use proc_macro2::{Ident, Span};
-use quote::quote;
-use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, Generics};
+use proc_macro2::{Ident, Span, TokenTree};
+use quote::{quote, quote_spanned};
+use syn::{
+    parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, Attribute, Data,
+    DeriveInput, Expr, ExprLit, ExprUnary, Fields, GenericParam, Generics, Lit, LitInt, LitStr,
+    Meta, Token, Type, TypeParamBound, UnOp, Variant, WherePredicate,
+};
 
-// Add a bound `T: Binary` to every type parameter T.
-fn add_trait_bounds(mut generics: Generics) -> Generics {
+// Add the given bound to every type parameter T, unless the type overrides its bounds with
+// `#[binary(bound = "...")]` or the parameter is marked `#[binary(no_bound)]`. Those attributes
+// are removed, as they mean nothing in the generated impls.
+fn add_trait_bounds(
+    mut generics: Generics,
+    container: &ContainerAttrs,
+    bound: TypeParamBound,
+) -> syn::Result<Generics> {
     for param in &mut generics.params {
         if let GenericParam::Type(ref mut type_param) = *param {
-            type_param.bounds.push(parse_quote!(Binary));
+            let mut no_bound = false;
+            for attr in &type_param.attrs {
+                if !attr.path().is_ident("binary") {
+                    continue;
+                }
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("no_bound") {
+                        no_bound = true;
+                        Ok(())
+                    } else {
+                        Err(meta.error("unrecognized binary attribute"))
+                    }
+                })?;
+            }
+            type_param
+                .attrs
+                .retain(|attr| !attr.path().is_ident("binary"));
+            if container.bound.is_none() && !no_bound {
+                type_param.bounds.push(bound.clone());
+            }
         }
     }
-    generics
+    if let Some(predicates) = &container.bound {
+        generics
+            .make_where_clause()
+            .predicates
+            .extend(predicates.iter().cloned());
+    }
+    Ok(generics)
 }
 
-#[proc_macro_derive(Binary)]
-pub fn derive_binary(tokens: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(tokens as DeriveInput);
-    let ty_name = &input.ident;
-    let generics = add_trait_bounds(input.generics);
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
-    match input.data {
-        Data::Struct(s) => {
-            match s.fields {
-                Fields::Named(fields) => {
-                    let parse_code = fields.named.iter().map(|field| {
-                        let field_ident = &field.ident.as_ref().unwrap();
-                        let field_ty = &field.ty;
-                        quote! {
-                            let (#field_ident, bs) = <#field_ty as Binary>::parse(bs)?;
-                        }
-                    });
-                    let field_names = fields
-                        .named
-                        .iter()
-                        .map(|field| &field.ident)
-                        .collect::<Vec<_>>();
-                    let unparse_code = fields.named.iter().map(|field| {
-                        let field_ident = &field.ident;
-                        quote! {
-                            #field_ident.unparse(bs);
-                        }
-                    });
-                    quote! {
-                        impl #impl_generics Binary for #ty_name #ty_generics #where_clause {
-                            fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-                                #(#parse_code);*
-                                Some((#ty_name { #(#field_names),* }, bs))
-                            }
+// Options set on the type itself via `#[binary(...)]`.
+#[derive(Default)]
+struct ContainerAttrs {
+    magic: Option<u32>,
+    version: Option<u16>,
+    // the tags reserved for future variants, as a half open range
+    reserved: Option<(u16, u16)>,
+    // whether to derive `BigEndian` as well
+    both_endian: bool,
+    // whether to derive `FixedSize` as well
+    fixed_size: bool,
+    // whether to derive `BinaryBorrow` as well
+    borrow: bool,
+    // the integer type named by `#[repr(...)]`, if the enum is also marked `#[binary(repr_tag)]`
+    // to encode its tags as that type
+    repr: Option<Ident>,
+    // the bounds replacing those placed on every type parameter
+    bound: Option<Punctuated<WherePredicate, Token![,]>>,
+}
 
-                            fn unparse(&self, bs: &mut Vec<u8>) {
-                                let #ty_name { #(#field_names),* } = &self;
-                                #(#unparse_code);*
-                            }
-                        }
+fn container_attrs(attrs: &[Attribute]) -> syn::Result<ContainerAttrs> {
+    let mut container = ContainerAttrs::default();
+    let mut repr = None;
+    let mut repr_tag = None;
+    for attr in attrs {
+        if attr.path().is_ident("repr") {
+            let reprs = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+            for meta in reprs {
+                let Meta::Path(path) = meta else {
+                    continue;
+                };
+                if let Some(ident) = path.get_ident() {
+                    let integers = [
+                        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+                        "i128", "isize",
+                    ];
+                    if integers.contains(&ident.to_string().as_str()) {
+                        repr = Some(ident.clone());
                     }
-                    .into()
                 }
-                Fields::Unnamed(fields) => {
-                    let field_idents = {
-                        let mut v = Vec::new();
-                        for i in 0..fields.unnamed.len() {
-                            v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
-                        }
-                        v
-                    };
-                    let parse_code = fields.unnamed.iter().zip(field_idents.iter()).map(
-                        |(field, field_ident)| {
-                            let field_ty = &field.ty;
-                            quote! {
-                                let (#field_ident, bs) = <#field_ty as Binary>::parse(bs)?;
-                            }
-                        },
-                    );
-                    let unparse_code = field_idents.iter().map(|field_ident| {
-                        quote! {
-                            #field_ident.unparse(bs);
-                        }
-                    });
-                    quote! {
-                        impl #impl_generics Binary for #ty_name #ty_generics #where_clause {
-                            fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-                                #(#parse_code);*
-                                Some((#ty_name ( #(#field_idents),* ), bs))
-                            }
-
-                            fn unparse(&self, bs: &mut Vec<u8>) {
-                                let #ty_name (#(#field_idents),*) = &self;
-                                #(#unparse_code);*
-                            }
-                        }
-                    }
-                    .into()
+            }
+            continue;
+        }
+        if !attr.path().is_ident("binary") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("magic") {
+                let magic: LitInt = meta.value()?.parse()?;
+                container.magic = Some(magic.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("version") {
+                let version: LitInt = meta.value()?.parse()?;
+                container.version = Some(version.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("both_endian") {
+                container.both_endian = true;
+                Ok(())
+            } else if meta.path.is_ident("fixed_size") {
+                container.fixed_size = true;
+                Ok(())
+            } else if meta.path.is_ident("borrow") {
+                container.borrow = true;
+                Ok(())
+            } else if meta.path.is_ident("repr_tag") {
+                repr_tag = Some(meta.path.span());
+                Ok(())
+            } else if meta.path.is_ident("bound") {
+                let bound: LitStr = meta.value()?.parse()?;
+                container.bound = Some(bound.parse_with(Punctuated::parse_terminated)?);
+                Ok(())
+            } else if meta.path.is_ident("reserved") {
+                let input = meta.value()?;
+                let start: LitInt = input.parse()?;
+                let inclusive = input.peek(Token![..=]);
+                if inclusive {
+                    input.parse::<Token![..=]>()?;
+                } else {
+                    input.parse::<Token![..]>()?;
                 }
-                Fields::Unit => quote! {
-                    impl #impl_generics Binary for #ty_name #ty_generics #where_clause {
-                        fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-                            return Some((#ty_name, bs));
-                        }
+                let end: LitInt = input.parse()?;
+                let start: u16 = start.base10_parse()?;
+                let end: u16 = end.base10_parse::<u16>()? + inclusive as u16;
+                if start >= end || end > 256 {
+                    return Err(meta.error("reserved tags must be a nonempty range within 0..256"));
+                }
+                container.reserved = Some((start, end));
+                Ok(())
+            } else {
+                Err(meta.error("unrecognized binary attribute"))
+            }
+        })?;
+    }
+    // the repr only changes the encoding when asked to, so that adding one to an enum, or
+    // deriving `Binary` for an enum which has one, doesn't change its tags
+    if let Some(span) = repr_tag {
+        match repr {
+            Some(repr) if repr == "usize" || repr == "isize" => {
+                return Err(syn::Error::new(
+                    repr.span(),
+                    "repr tags can't be usize or isize, whose size depends on the platform",
+                ))
+            }
+            Some(repr) => container.repr = Some(repr),
+            None => {
+                return Err(syn::Error::new(
+                    span,
+                    "repr_tag requires an integer repr, such as #[repr(u16)]",
+                ))
+            }
+        }
+    }
+    Ok(container)
+}
 
-                        fn unparse(&self, bs: &mut Vec<u8>) {}
-                    }
+// Options set on a field via `#[binary(...)]`.
+#[derive(Default)]
+struct FieldAttrs {
+    order: Option<usize>,
+    // read as `Default::default()` when the input runs out before the field
+    default: bool,
+    // a derived struct whose fields are serialized inline, which they are anyway, but this
+    // checks that the field's type is one
+    flatten: bool,
+}
+
+fn field_attrs(attrs: &[Attribute]) -> syn::Result<FieldAttrs> {
+    let mut field = FieldAttrs::default();
+    for attr in attrs {
+        if !attr.path().is_ident("binary") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("order") {
+                let n: LitInt = meta.value()?.parse()?;
+                field.order = Some(n.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("default") {
+                field.default = true;
+                Ok(())
+            } else if meta.path.is_ident("flatten") {
+                field.flatten = true;
+                Ok(())
+            } else {
+                Err(meta.error("unrecognized binary attribute"))
+            }
+        })?;
+    }
+    Ok(field)
+}
+
+// The indices of the fields in the order they are serialized. This is the declaration order
+// unless the fields are annotated with `#[binary(order = N)]`, in which case every field must
+// be, and the orders must be 0 through the number of fields minus one.
+fn serialization_order(fields: &Fields) -> Result<Vec<usize>, proc_macro2::TokenStream> {
+    let mut orders = Vec::new();
+    for field in fields.iter() {
+        match field_attrs(&field.attrs) {
+            Ok(field) => orders.push(field.order),
+            Err(e) => return Err(e.to_compile_error()),
+        }
+    }
+    if orders.iter().all(Option::is_none) {
+        return Ok((0..orders.len()).collect());
+    }
+    let mut indices = vec![None; orders.len()];
+    for (i, order) in orders.into_iter().enumerate() {
+        match order {
+            Some(order) if order < indices.len() && indices[order].is_none() => {
+                indices[order] = Some(i);
+            }
+            _ => {
+                return Err(quote! {
+                    compile_error!("binary field orders must be a permutation of the field indices")
+                })
+            }
+        }
+    }
+    Ok(indices.into_iter().map(Option::unwrap).collect())
+}
+
+// Whether each field, by declaration index, is marked `#[binary(default)]`. Such fields may only
+// come last in the serialization order, so that bytes written before they were added still parse.
+fn default_fields(fields: &Fields, order: &[usize]) -> Result<Vec<bool>, proc_macro2::TokenStream> {
+    let mut defaults = Vec::new();
+    for field in fields.iter() {
+        match field_attrs(&field.attrs) {
+            Ok(field) => defaults.push(field.default),
+            Err(e) => return Err(e.to_compile_error()),
+        }
+    }
+    let mut seen_default = false;
+    for i in order {
+        if seen_default && !defaults[*i] {
+            return Err(quote! {
+                compile_error!("binary default fields must come after every other field")
+            });
+        }
+        seen_default |= defaults[*i];
+    }
+    Ok(defaults)
+}
+
+// Parses a field into a variable with the given trait method, or defaults it if it's marked
+// `#[binary(default)]` and the input has run out.
+fn parse_field(
+    ident: &Ident,
+    ty: &Type,
+    default: bool,
+    (trait_name, parse): (proc_macro2::TokenStream, &str),
+) -> proc_macro2::TokenStream {
+    let parse = Ident::new(parse, Span::call_site());
+    if default {
+        quote! {
+            let (#ident, bs) = if bs.is_empty() {
+                (<#ty as Default>::default(), bs)
+            } else {
+                <#ty as #trait_name>::#parse(bs)?
+            };
+        }
+    } else {
+        quote! {
+            let (#ident, bs) = <#ty as #trait_name>::#parse(bs)?;
+        }
+    }
+}
+
+// Whether a variant is marked `#[binary(unknown)]`, to hold values with unrecognized tags.
+fn is_unknown_variant(variant: &Variant) -> syn::Result<bool> {
+    let mut unknown = false;
+    for attr in &variant.attrs {
+        if !attr.path().is_ident("binary") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("unknown") {
+                unknown = true;
+                Ok(())
+            } else {
+                Err(meta.error("unrecognized binary attribute"))
+            }
+        })?;
+    }
+    Ok(unknown)
+}
+
+// The variant marked `#[binary(unknown)]`, if any, which must be the last variant and must hold
+// a tag and the bytes following it.
+fn unknown_variant(variants: &[&Variant]) -> Result<Option<Ident>, proc_macro2::TokenStream> {
+    let mut unknown = None;
+    for (i, variant) in variants.iter().enumerate() {
+        if !is_unknown_variant(variant).map_err(|e| e.to_compile_error())? {
+            continue;
+        }
+        if i + 1 != variants.len() {
+            return Err(quote! { compile_error!("the unknown variant must be the last variant") });
+        }
+        match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 2 => {}
+            _ => {
+                return Err(quote! {
+                    compile_error!("the unknown variant must have the shape (u8, Vec<u8>)")
+                })
+            }
+        }
+        unknown = Some(variant.ident.clone());
+    }
+    Ok(unknown)
+}
+
+// The tag of each variant, which is its index as a `u8`, unless the enum is marked
+// `#[binary(repr_tag)]`, in which case it's its discriminant as its integer `#[repr(...)]`.
+// Explicit discriminants must then be integer literals.
+fn variant_tags(
+    container: &ContainerAttrs,
+    variants: &Punctuated<Variant, Token![,]>,
+) -> Result<Vec<proc_macro2::TokenStream>, proc_macro2::TokenStream> {
+    if container.repr.is_none() {
+        // supports enums of up to 256 variants
+        if variants.len() > 256 {
+            return Err(quote! { compile_error!("more than 256 variants") });
+        }
+        return Ok((0..variants.len())
+            .map(|i| {
+                let tag = i as u8;
+                quote! { #tag }
+            })
+            .collect());
+    }
+    let mut discriminant = 0i128;
+    let mut tags = Vec::new();
+    for variant in variants {
+        if let Some((_, expr)) = &variant.discriminant {
+            discriminant = integer_literal(expr).ok_or_else(|| {
+                quote! {
+                    compile_error!("binary repr enums must have integer literal discriminants")
                 }
-                .into(),
+            })?;
+        }
+        let magnitude = proc_macro2::Literal::u128_unsuffixed(discriminant.unsigned_abs());
+        tags.push(if discriminant < 0 {
+            quote! { -#magnitude }
+        } else {
+            quote! { #magnitude }
+        });
+        discriminant += 1;
+    }
+    Ok(tags)
+}
+
+fn integer_literal(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(n), ..
+        }) => n.base10_parse().ok(),
+        Expr::Unary(ExprUnary {
+            op: UnOp::Neg(_),
+            expr,
+            ..
+        }) => integer_literal(expr).map(|n| -n),
+        Expr::Group(group) => integer_literal(&group.expr),
+        Expr::Paren(paren) => integer_literal(&paren.expr),
+        _ => None,
+    }
+}
+
+// Code checking and writing the magic and version header, if any, ahead of the fields.
+fn header_code(container: &ContainerAttrs) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let mut parse_code = Vec::new();
+    let mut unparse_code = Vec::new();
+    if let Some(magic) = container.magic {
+        parse_code.push(quote! {
+            let (magic, bs) = <u32 as Binary>::parse(bs)?;
+            if magic != #magic {
+                return None;
+            }
+        });
+        unparse_code.push(quote! {
+            <u32 as Binary>::unparse(&#magic, bs);
+        });
+    }
+    if let Some(version) = container.version {
+        parse_code.push(quote! {
+            let (version, bs) = <u16 as Binary>::parse(bs)?;
+            if version > #version {
+                return None;
             }
+        });
+        unparse_code.push(quote! {
+            <u16 as Binary>::unparse(&#version, bs);
+        });
+    }
+    (quote! { #(#parse_code)* }, quote! { #(#unparse_code)* })
+}
+
+// For structs marked `#[binary(fixed_size)]`, an impl of `FixedSize` whose size is that of the
+// magic number and version, if any, plus those of the fields, each of which must be `FixedSize`.
+fn fixed_size_impl(
+    container: &ContainerAttrs,
+    ty_name: &Ident,
+    generics: &Generics,
+    data: &Data,
+) -> Result<proc_macro2::TokenStream, proc_macro2::TokenStream> {
+    let Data::Struct(s) = data else {
+        return Err(quote! { compile_error!("only structs can be fixed size") });
+    };
+    let header_size: usize = container.magic.map_or(0, |_| 4) + container.version.map_or(0, |_| 2);
+    let field_tys = s.fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let mut generics = generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &field_tys {
+        where_clause
+            .predicates
+            .push(parse_quote!(#ty: ::binary::FixedSize));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::binary::FixedSize for #ty_name #ty_generics #where_clause {
+            const SIZE: usize = #header_size #(+ <#field_tys as ::binary::FixedSize>::SIZE)*;
         }
+    })
+}
+
+// The bodies of the methods reading and writing the fields, where `parse` and `write` name the
+// methods used to read and write each field.
+fn bodies(
+    container: &ContainerAttrs,
+    ty_name: &Ident,
+    data: &Data,
+    parse: &str,
+    write: &Ident,
+) -> Result<(proc_macro2::TokenStream, proc_macro2::TokenStream), proc_macro2::TokenStream> {
+    let parse_ident = Ident::new(parse, Span::call_site());
+    let bodies = match data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(fields) => {
+                let order = serialization_order(&s.fields)?;
+                let defaults = default_fields(&s.fields, &order)?;
+                let ordered = order.iter().map(|i| &fields.named[*i]).collect::<Vec<_>>();
+                let parse_code = order.iter().map(|i| {
+                    let field = &fields.named[*i];
+                    parse_field(
+                        field.ident.as_ref().unwrap(),
+                        &field.ty,
+                        defaults[*i],
+                        (quote! { Binary }, parse),
+                    )
+                });
+                let field_names = fields
+                    .named
+                    .iter()
+                    .map(|field| &field.ident)
+                    .collect::<Vec<_>>();
+                let unparse_code = ordered.iter().map(|field| {
+                    let field_ident = &field.ident;
+                    quote! {
+                        #field_ident.#write(bs);
+                    }
+                });
+                (
+                    quote! {
+                        #(#parse_code);*
+                        Some((#ty_name { #(#field_names),* }, bs))
+                    },
+                    quote! {
+                        let #ty_name { #(#field_names),* } = &self;
+                        #(#unparse_code);*
+                    },
+                )
+            }
+            Fields::Unnamed(fields) => {
+                let order = serialization_order(&s.fields)?;
+                let field_idents = {
+                    let mut v = Vec::new();
+                    for i in 0..fields.unnamed.len() {
+                        v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
+                    }
+                    v
+                };
+                let defaults = default_fields(&s.fields, &order)?;
+                let parse_code = order.iter().map(|i| {
+                    parse_field(
+                        &field_idents[*i],
+                        &fields.unnamed[*i].ty,
+                        defaults[*i],
+                        (quote! { Binary }, parse),
+                    )
+                });
+                let unparse_code = order.iter().map(|i| &field_idents[*i]).map(|field_ident| {
+                    quote! {
+                        #field_ident.#write(bs);
+                    }
+                });
+                (
+                    quote! {
+                        #(#parse_code);*
+                        Some((#ty_name ( #(#field_idents),* ), bs))
+                    },
+                    quote! {
+                        let #ty_name (#(#field_idents),*) = &self;
+                        #(#unparse_code);*
+                    },
+                )
+            }
+            Fields::Unit => (
+                quote! {
+                    return Some((#ty_name, bs));
+                },
+                quote! {},
+            ),
+        },
         Data::Enum(e) => {
-            // supports enums of up to 256 variants
-            if e.variants.len() > 256 {
-                return quote! { compile_error!("more than 256 variants") }.into();
-            }
-            let parse_match_branches = e.variants.iter().zip(0u8..).map(|(variant, tag)| {
-                let variant_ident = &variant.ident;
-                match &variant.fields {
-                    Fields::Named(fields) => {
-                        let parse_code = fields.named.iter().map(|field| {
-                            let field_ident = &field.ident;
-                            let field_ty = &field.ty;
-                            quote! {
-                                let (#field_ident, bs) = <#field_ty as Binary>::parse(bs)?;
-                            }
+            let tags = variant_tags(container, &e.variants)?;
+            let orders = e
+                .variants
+                .iter()
+                .map(|variant| serialization_order(&variant.fields))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (variant, order) in e.variants.iter().zip(&orders) {
+                if default_fields(&variant.fields, order)?.contains(&true) {
+                    return Err(quote! {
+                        compile_error!("only the fields of structs can be binary default fields")
+                    });
+                }
+                for field in variant.fields.iter() {
+                    if field_attrs(&field.attrs)
+                        .map_err(|e| e.to_compile_error())?
+                        .flatten
+                    {
+                        return Err(quote! {
+                            compile_error!("only the fields of structs can be flattened")
                         });
-                        let field_names = fields
-                            .named
-                            .iter()
-                            .map(|field| &field.ident)
-                            .collect::<Vec<_>>();
-                        quote! {
-                            #tag => {
-                                #(#parse_code);*
-                                Some((#ty_name::#variant_ident { #(#field_names),* }, bs))
-                            }
-                        }
                     }
-                    Fields::Unnamed(fields) => {
-                        let field_idents = {
-                            let mut v = Vec::new();
-                            for i in 0..fields.unnamed.len() {
-                                v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
-                            }
-                            v
-                        };
-                        let parse_code = fields.unnamed.iter().zip(field_idents.iter()).map(
-                            |(field, field_ident)| {
+                }
+            }
+            let unknown = unknown_variant(&e.variants.iter().collect::<Vec<_>>())?;
+            if unknown.is_some() && container.repr.is_some() {
+                return Err(quote! {
+                    compile_error!("enums with an integer repr can't have an unknown variant")
+                });
+            }
+            let (read_tag, write_tag) = match &container.repr {
+                Some(repr) => (
+                    quote! { let (b, bs) = <#repr as Binary>::parse(bs)?; },
+                    quote! { <#repr as Binary>::unparse },
+                ),
+                None => (
+                    quote! {
+                        if bs.len() == 0 {
+                            return None;
+                        }
+                        let b = bs[0];
+                        let bs = &bs[1..];
+                    },
+                    quote! { <u8 as Binary>::unparse },
+                ),
+            };
+            let known =
+                |((variant, _), _): &((&Variant, _), _)| Some(&variant.ident) != unknown.as_ref();
+            let parse_match_branches = e.variants.iter().zip(&orders).zip(&tags).filter(known).map(
+                |((variant, order), tag)| {
+                    let variant_ident = &variant.ident;
+                    match &variant.fields {
+                        Fields::Named(fields) => {
+                            let parse_code = order.iter().map(|i| &fields.named[*i]).map(|field| {
+                                let field_ident = &field.ident;
                                 let field_ty = &field.ty;
                                 quote! {
-                                    let (#field_ident, bs) = <#field_ty as Binary>::parse(bs)?;
+                                    let (#field_ident, bs) =
+                                        <#field_ty as Binary>::#parse_ident(bs)?;
+                                }
+                            });
+                            let field_names = fields
+                                .named
+                                .iter()
+                                .map(|field| &field.ident)
+                                .collect::<Vec<_>>();
+                            quote! {
+                                #tag => {
+                                    #(#parse_code);*
+                                    Some((#ty_name::#variant_ident { #(#field_names),* }, bs))
                                 }
-                            },
-                        );
-                        quote! {
-                            #tag => {
-                                #(#parse_code);*
-                                Some((#ty_name::#variant_ident ( #(#field_idents),* ), bs))
                             }
                         }
-                    }
-                    Fields::Unit => {
-                        quote! {
-                            #tag => {
-                                return Some((#ty_name::#variant_ident, bs));
+                        Fields::Unnamed(fields) => {
+                            let field_idents = {
+                                let mut v = Vec::new();
+                                for i in 0..fields.unnamed.len() {
+                                    v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
+                                }
+                                v
+                            };
+                            let parse_code = order.iter().map(|i| {
+                                let field_ty = &fields.unnamed[*i].ty;
+                                let field_ident = &field_idents[*i];
+                                quote! {
+                                    let (#field_ident, bs) =
+                                        <#field_ty as Binary>::#parse_ident(bs)?;
+                                }
+                            });
+                            quote! {
+                                #tag => {
+                                    #(#parse_code);*
+                                    Some((#ty_name::#variant_ident ( #(#field_idents),* ), bs))
+                                }
                             }
                         }
-                    }
-                }
-            });
-            let unparse_match_branches = e.variants.iter().zip(0u8..).map(|(variant, tag)| {
-                let variant_ident = &variant.ident;
-                match &variant.fields {
-                    Fields::Named(fields) => {
-                        let unparse_code = fields.named.iter().map(|field| {
-                            let field_ident = &field.ident;
+                        Fields::Unit => {
                             quote! {
-                                #field_ident.unparse(bs);
-                            }
-                        });
-                        let field_names = fields
-                            .named
-                            .iter()
-                            .map(|field| &field.ident)
-                            .collect::<Vec<_>>();
-                        quote! {
-                            #ty_name::#variant_ident { #(#field_names),* } => {
-                                bs.push(#tag);
-                                #(#unparse_code);*
+                                #tag => {
+                                    return Some((#ty_name::#variant_ident, bs));
+                                }
                             }
                         }
                     }
-                    Fields::Unnamed(fields) => {
-                        let field_idents = {
-                            let mut v = Vec::new();
-                            for i in 0..fields.unnamed.len() {
-                                v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
+                },
+            );
+            let unparse_match_branches = e
+                .variants
+                .iter()
+                .zip(&orders)
+                .zip(&tags)
+                .filter(known)
+                .map(|((variant, order), tag)| {
+                    let variant_ident = &variant.ident;
+                    match &variant.fields {
+                        Fields::Named(fields) => {
+                            let unparse_code =
+                                order.iter().map(|i| &fields.named[*i]).map(|field| {
+                                    let field_ident = &field.ident;
+                                    quote! {
+                                        #field_ident.#write(bs);
+                                    }
+                                });
+                            let field_names = fields
+                                .named
+                                .iter()
+                                .map(|field| &field.ident)
+                                .collect::<Vec<_>>();
+                            quote! {
+                                #ty_name::#variant_ident { #(#field_names),* } => {
+                                    #write_tag(&(#tag), bs);
+                                    #(#unparse_code);*
+                                }
                             }
-                            v
-                        };
-                        let unparse_code = fields.unnamed.iter().zip(field_idents.iter()).map(
-                            |(_field, field_ident)| {
+                        }
+                        Fields::Unnamed(fields) => {
+                            let field_idents = {
+                                let mut v = Vec::new();
+                                for i in 0..fields.unnamed.len() {
+                                    v.push(Ident::new(&format!("field_{}", i), Span::call_site()));
+                                }
+                                v
+                            };
+                            let unparse_code = order.iter().map(|i| {
+                                let field_ident = &field_idents[*i];
                                 quote! {
-                                    #field_ident.unparse(bs);
+                                    #field_ident.#write(bs);
+                                }
+                            });
+                            quote! {
+                                #ty_name::#variant_ident (#(#field_idents),*) => {
+                                    #write_tag(&(#tag), bs);
+                                    #(#unparse_code);*
                                 }
-                            },
-                        );
-                        quote! {
-                            #ty_name::#variant_ident (#(#field_idents),*) => {
-                                bs.push(#tag);
-                                #(#unparse_code);*
                             }
                         }
-                    }
-                    Fields::Unit => {
-                        quote! {
-                            #ty_name::#variant_ident => {
-                                bs.push(#tag);
+                        Fields::Unit => {
+                            quote! {
+                                #ty_name::#variant_ident => {
+                                    #write_tag(&(#tag), bs);
+                                }
                             }
                         }
                     }
-                }
-            });
-            quote! {
-                impl #impl_generics Binary for #ty_name #ty_generics #where_clause {
-                    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-                        if bs.len() == 0 {
-                            return None;
+                });
+            // the unknown variant takes the tag and all the bytes after it, and writes them back
+            let (parse_unknown, unparse_unknown) = match &unknown {
+                Some(unknown) => (
+                    quote! { Some((#ty_name::#unknown(b, bs.to_vec()), &bs[bs.len()..])) },
+                    quote! {
+                        #ty_name::#unknown(tag, rest) => {
+                            bs.push(*tag);
+                            bs.write_bytes(rest);
                         }
-                        let b = bs[0];
-                        let bs = &bs[1..];
+                    },
+                ),
+                None => (quote! { None }, quote! {}),
+            };
+            if e.variants.is_empty() {
+                // there are no values to encode and none can be parsed
+                (quote! { None }, quote! { match *self {} })
+            } else {
+                (
+                    quote! {
+                        #read_tag
                         match b {
                             #(#parse_match_branches)*
-                            _ => None
+                            _ => #parse_unknown
                         }
-                    }
-
-                    fn unparse(&self, bs: &mut Vec<u8>) {
+                    },
+                    quote! {
                         match self {
                             #(#unparse_match_branches)*
+                            #unparse_unknown
                         }
-                    }
+                    },
+                )
+            }
+        }
+        _ => {
+            return Err(
+                quote! { compile_error!("Binary can only be derived on structs and enums") },
+            )
+        }
+    };
+    Ok(bodies)
+}
+
+// For structs, the body of `FieldOffsets::field_offsets`, reporting the name, offset, and length
+// of each field in the order they are serialized. Tuple struct fields are named by their index.
+// The types of fields marked `#[binary(flatten)]` must implement `FieldOffsets` too, meaning
+// they are derived structs.
+fn field_offsets_body(
+    header_parse: &proc_macro2::TokenStream,
+    data: &Data,
+) -> Result<Option<proc_macro2::TokenStream>, proc_macro2::TokenStream> {
+    let Data::Struct(s) = data else {
+        return Ok(None);
+    };
+    let order = serialization_order(&s.fields)?;
+    let defaults = default_fields(&s.fields, &order)?;
+    let fields = s.fields.iter().collect::<Vec<_>>();
+    let mut flattened = Vec::new();
+    for field in &fields {
+        if field_attrs(&field.attrs)
+            .map_err(|e| e.to_compile_error())?
+            .flatten
+        {
+            let field_ty = &field.ty;
+            flattened.push(quote_spanned! { field_ty.span() =>
+                let _ = <#field_ty as ::binary::FieldOffsets>::field_offsets;
+            });
+        }
+    }
+    let offset_code = order.iter().map(|i| {
+        let field = fields[*i];
+        let name = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None => i.to_string(),
+        };
+        let field_ty = &field.ty;
+        let missing = if defaults[*i] {
+            quote! {
+                if bs.is_empty() {
+                    return Some(offsets);
                 }
             }
-            .into()
+        } else {
+            quote! {}
+        };
+        quote! {
+            #missing
+            let offset = start - bs.len();
+            let (_, bs) = <#field_ty as Binary>::parse(bs)?;
+            offsets.push((#name, offset, start - bs.len() - offset));
         }
-        _ => quote! { compile_error!("Binary can only be derived on structs and enums") }.into(),
+    });
+    Ok(Some(quote! {
+        #(#flattened)*
+        let start = bs.len();
+        #header_parse
+        let mut offsets = Vec::new();
+        #(#offset_code)*
+        Some(offsets)
+    }))
+}
+
+// A human readable description of the type, listing its fields in the order they are
+// serialized, such as `Example { a: u128, b: i64 }` or `Shape { Circle(f64), Point }`.
+fn schema(
+    container: &ContainerAttrs,
+    ty_name: &Ident,
+    data: &Data,
+) -> Result<String, proc_macro2::TokenStream> {
+    fn type_name(ty: &Type) -> String {
+        quote!(#ty)
+            .to_string()
+            .replace(" :: ", "::")
+            .replace(" <", "<")
+            .replace("< ", "<")
+            .replace(" >", ">")
+            .replace(" ,", ",")
+    }
+    fn fields_schema(fields: &Fields) -> Result<String, proc_macro2::TokenStream> {
+        let order = serialization_order(fields)?;
+        let fields = fields.iter().collect::<Vec<_>>();
+        let described = order.iter().map(|i| {
+            let field = fields[*i];
+            match &field.ident {
+                Some(ident) => format!("{}: {}", ident, type_name(&field.ty)),
+                None => type_name(&field.ty),
+            }
+        });
+        let described = described.collect::<Vec<_>>().join(", ");
+        Ok(match fields.first().map(|field| &field.ident) {
+            Some(Some(_)) => format!(" {{ {} }}", described),
+            Some(None) => format!("({})", described),
+            None => String::new(),
+        })
+    }
+    match data {
+        Data::Struct(s) => Ok(format!("{}{}", ty_name, fields_schema(&s.fields)?)),
+        Data::Enum(e) => {
+            // enums with repr tags list the type of their tags and the tag of each variant, as
+            // in `Opcode: u16 { Nop = 0, Halt = 65535 }`
+            let (name, tags) = match &container.repr {
+                Some(repr) => (
+                    format!("{}: {}", ty_name, repr),
+                    variant_tags(container, &e.variants)?
+                        .iter()
+                        .map(|tag| format!(" = {}", tag.to_string().replace(' ', "")))
+                        .collect(),
+                ),
+                None => (ty_name.to_string(), vec![String::new(); e.variants.len()]),
+            };
+            let mut variants = Vec::new();
+            for (variant, tag) in e.variants.iter().zip(tags) {
+                variants.push(format!(
+                    "{}{}{}",
+                    variant.ident,
+                    fields_schema(&variant.fields)?,
+                    tag
+                ));
+            }
+            if variants.is_empty() {
+                Ok(format!("{} {{}}", name))
+            } else {
+                Ok(format!("{} {{ {} }}", name, variants.join(", ")))
+            }
+        }
+        _ => Err(quote! { compile_error!("Binary can only be derived on structs and enums") }),
+    }
+}
+
+// For enums with reserved tags, an inherent `try_parse` method distinguishing reserved tags from
+// invalid input.
+fn try_parse_method(
+    container: &ContainerAttrs,
+    header_parse: &proc_macro2::TokenStream,
+    data: &Data,
+) -> Result<proc_macro2::TokenStream, proc_macro2::TokenStream> {
+    let Some((start, end)) = container.reserved else {
+        return Ok(quote! {});
+    };
+    if container.repr.is_some() {
+        return Err(quote! { compile_error!("enums with an integer repr can't reserve tags") });
+    }
+    let Data::Enum(e) = data else {
+        return Err(quote! { compile_error!("only enums can reserve tags") });
+    };
+    if (start as usize) < e.variants.len() {
+        return Err(
+            quote! { compile_error!("reserved tags overlap the tags of declared variants") },
+        );
+    }
+    Ok(quote! {
+        /// Parses like `Binary::parse`, but reports a tag reserved for future variants as
+        /// `BinaryError::ReservedTag` rather than as invalid input.
+        pub fn try_parse(bs: &[u8]) -> Result<(Self, &[u8]), ::binary::BinaryError> {
+            let tag = (|bs: &[u8]| -> Option<u8> {
+                #header_parse
+                bs.first().copied()
+            })(bs);
+            if let Some(tag) = tag {
+                if (#start..#end).contains(&(tag as u16)) {
+                    return Err(::binary::BinaryError::ReservedTag(tag));
+                }
+            }
+            <Self as Binary>::parse(bs).ok_or(::binary::BinaryError::Invalid)
+        }
+    })
+}
+
+// For `#[binary(both_endian)]` structs, the bodies of `parse_be` and `unparse_be`, which read
+// and write the fields in big endian order.
+fn big_endian_bodies(
+    ty_name: &Ident,
+    data: &Data,
+) -> Result<(proc_macro2::TokenStream, proc_macro2::TokenStream), proc_macro2::TokenStream> {
+    let Data::Struct(s) = data else {
+        return Err(quote! { compile_error!("only structs can be both_endian") });
+    };
+    let order = serialization_order(&s.fields)?;
+    let defaults = default_fields(&s.fields, &order)?;
+    let fields = s.fields.iter().collect::<Vec<_>>();
+    let field_idents = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => Ident::new(&format!("field_{}", i), Span::call_site()),
+        })
+        .collect::<Vec<_>>();
+    let parse_code = order.iter().map(|i| {
+        parse_field(
+            &field_idents[*i],
+            &fields[*i].ty,
+            defaults[*i],
+            (quote! { ::binary::BigEndian }, "parse_be"),
+        )
+    });
+    let unparse_code = order.iter().map(|i| {
+        let field_ident = &field_idents[*i];
+        quote! {
+            ::binary::BigEndian::unparse_be(#field_ident, bs);
+        }
+    });
+    let pattern = match &s.fields {
+        Fields::Named(_) => quote! { #ty_name { #(#field_idents),* } },
+        Fields::Unnamed(_) => quote! { #ty_name ( #(#field_idents),* ) },
+        Fields::Unit => quote! { #ty_name },
+    };
+    Ok((
+        quote! {
+            #(#parse_code)*
+            Some((#pattern, bs))
+        },
+        quote! {
+            let #pattern = &self;
+            #(#unparse_code)*
+        },
+    ))
+}
+
+// Whether a type mentions a lifetime, and so may borrow from the input.
+fn mentions_lifetime(tokens: proc_macro2::TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Punct(punct) => punct.as_char() == '\'',
+        TokenTree::Group(group) => mentions_lifetime(group.stream()),
+        _ => false,
+    })
+}
+
+// For `#[binary(borrow)]` structs, which can't be parsed without borrowing, impls of `Encode` and
+// of `BinaryBorrow` for their one lifetime parameter rather than of `Binary`. Fields whose types
+// mention a lifetime are written with `Encode` and parsed with `BinaryBorrow`, and the rest with
+// `Binary`.
+fn borrow_impl(
+    container: &ContainerAttrs,
+    ty_name: &Ident,
+    generics: &Generics,
+    (header_parse, header_unparse): (&proc_macro2::TokenStream, &proc_macro2::TokenStream),
+    data: &Data,
+) -> Result<proc_macro2::TokenStream, proc_macro2::TokenStream> {
+    let Data::Struct(s) = data else {
+        return Err(quote! { compile_error!("only structs can be borrowed") });
+    };
+    let mut lifetimes = generics.lifetimes();
+    let (Some(lifetime), None) = (lifetimes.next(), lifetimes.next()) else {
+        return Err(quote! {
+            compile_error!("borrowed structs must have exactly one lifetime parameter")
+        });
+    };
+    if container.both_endian || container.fixed_size {
+        return Err(quote! {
+            compile_error!("borrowed structs can't be both_endian or fixed_size")
+        });
+    }
+    let lifetime = &lifetime.lifetime;
+    let order = serialization_order(&s.fields)?;
+    let defaults = default_fields(&s.fields, &order)?;
+    let fields = s.fields.iter().collect::<Vec<_>>();
+    let field_idents = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.clone(),
+            None => Ident::new(&format!("field_{}", i), Span::call_site()),
+        })
+        .collect::<Vec<_>>();
+    let parse_code = order.iter().map(|i| {
+        let ty = &fields[*i].ty;
+        let parse = if mentions_lifetime(quote!(#ty)) {
+            (
+                quote! { ::binary::BinaryBorrow<#lifetime> },
+                "parse_borrowed",
+            )
+        } else {
+            (quote! { Binary }, "parse")
+        };
+        parse_field(&field_idents[*i], ty, defaults[*i], parse)
+    });
+    let write_code = |write: &str| {
+        let write = Ident::new(write, Span::call_site());
+        order
+            .iter()
+            .map(|i| {
+                let ty = &fields[*i].ty;
+                let field_ident = &field_idents[*i];
+                if mentions_lifetime(quote!(#ty)) {
+                    quote! { ::binary::Encode::#write(#field_ident, bs); }
+                } else {
+                    quote! { ::binary::Binary::#write(#field_ident, bs); }
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let unparse_code = write_code("unparse");
+    let unparse_canonical_code = write_code("unparse_canonical");
+    let pattern = match &s.fields {
+        Fields::Named(_) => quote! { #ty_name { #(#field_idents),* } },
+        Fields::Unnamed(_) => quote! { #ty_name ( #(#field_idents),* ) },
+        Fields::Unit => quote! { #ty_name },
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::binary::Encode for #ty_name #ty_generics #where_clause {
+            fn unparse<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                #header_unparse
+                let #pattern = self;
+                #(#unparse_code)*
+            }
+
+            fn unparse_canonical<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                #header_unparse
+                let #pattern = self;
+                #(#unparse_canonical_code)*
+            }
+        }
+
+        impl #impl_generics ::binary::BinaryBorrow<#lifetime>
+            for #ty_name #ty_generics #where_clause
+        {
+            fn parse_borrowed(bs: &#lifetime [u8]) -> Option<(Self, &#lifetime [u8])> {
+                #header_parse
+                #(#parse_code)*
+                Some((#pattern, bs))
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Binary, attributes(binary))]
+pub fn derive_binary(tokens: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(tokens as DeriveInput);
+    let ty_name = &input.ident;
+    let container = match container_attrs(&input.attrs) {
+        Ok(container) => container,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if container.repr.is_some() && !matches!(input.data, Data::Enum(_)) {
+        return quote! { compile_error!("only enums can have repr tags") }.into();
+    }
+    let (header_parse, header_unparse) = header_code(&container);
+    let generics = match add_trait_bounds(input.generics.clone(), &container, parse_quote!(Binary))
+    {
+        Ok(generics) => generics,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    if container.borrow {
+        let header = (&header_parse, &header_unparse);
+        return match borrow_impl(&container, ty_name, &generics, header, &input.data) {
+            Ok(borrow) => borrow,
+            Err(e) => e,
+        }
+        .into();
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let unparse = Ident::new("unparse", Span::call_site());
+    let unparse_canonical = Ident::new("unparse_canonical", Span::call_site());
+    let (parse_body, unparse_body) =
+        match bodies(&container, ty_name, &input.data, "parse", &unparse) {
+            Ok(bodies) => bodies,
+            Err(e) => return e.into(),
+        };
+    let (parse_strict_body, unparse_canonical_body) = match bodies(
+        &container,
+        ty_name,
+        &input.data,
+        "parse_strict",
+        &unparse_canonical,
+    ) {
+        Ok(bodies) => bodies,
+        Err(e) => return e.into(),
+    };
+    let field_offsets = match field_offsets_body(&header_parse, &input.data) {
+        Ok(Some(body)) => quote! {
+            impl #impl_generics ::binary::FieldOffsets for #ty_name #ty_generics #where_clause {
+                #[allow(unused_mut, unused_variables)]
+                fn field_offsets(bs: &[u8]) -> Option<Vec<(&'static str, usize, usize)>> {
+                    #body
+                }
+            }
+        },
+        Ok(None) => quote! {},
+        Err(e) => return e.into(),
+    };
+    let schema = match schema(&container, ty_name, &input.data) {
+        Ok(schema) => schema,
+        Err(e) => return e.into(),
+    };
+    let try_parse = match try_parse_method(&container, &header_parse, &input.data) {
+        Ok(try_parse) => try_parse,
+        Err(e) => return e.into(),
+    };
+    let big_endian = if container.both_endian {
+        if container.bound.is_some() {
+            return quote! { compile_error!("structs with custom bounds can't be both_endian") }
+                .into();
+        }
+        let (parse_be_body, unparse_be_body) = match big_endian_bodies(ty_name, &input.data) {
+            Ok(bodies) => bodies,
+            Err(e) => return e.into(),
+        };
+        let generics = match add_trait_bounds(
+            input.generics.clone(),
+            &container,
+            parse_quote!(::binary::BigEndian),
+        ) {
+            Ok(generics) => generics,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics #ty_name #ty_generics #where_clause {
+                /// Parses the usual little endian encoding.
+                pub fn parse_le(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    <Self as ::binary::Binary>::parse(bs)
+                }
+
+                /// Writes the usual little endian encoding.
+                pub fn unparse_le<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                    <Self as ::binary::Binary>::unparse(self, bs)
+                }
+
+                /// Parses the big endian encoding.
+                pub fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    <Self as ::binary::BigEndian>::parse_be(bs)
+                }
+
+                /// Writes the big endian encoding.
+                pub fn unparse_be<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                    <Self as ::binary::BigEndian>::unparse_be(self, bs)
+                }
+            }
+
+            impl #impl_generics ::binary::BigEndian for #ty_name #ty_generics #where_clause {
+                fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    #header_parse
+                    #parse_be_body
+                }
+
+                fn unparse_be<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                    #header_unparse
+                    #unparse_be_body
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let fixed_size = if container.fixed_size {
+        match fixed_size_impl(&container, ty_name, &generics, &input.data) {
+            Ok(fixed_size) => fixed_size,
+            Err(e) => return e.into(),
+        }
+    } else {
+        quote! {}
+    };
+    quote! {
+        impl #impl_generics ::binary::Schema for #ty_name #ty_generics #where_clause {
+            const SCHEMA: &'static str = #schema;
+        }
+
+        impl #impl_generics #ty_name #ty_generics #where_clause {
+            #try_parse
+        }
+
+        #field_offsets
+
+        impl #impl_generics Binary for #ty_name #ty_generics #where_clause {
+            fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+                #header_parse
+                #parse_body
+            }
+
+            fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+                #header_parse
+                #parse_strict_body
+            }
+
+            fn unparse<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                #header_unparse
+                #unparse_body
+            }
+
+            fn unparse_canonical<S: ::binary::ByteSink + ?Sized>(&self, bs: &mut S) {
+                #header_unparse
+                #unparse_canonical_body
+            }
+        }
+
+        #big_endian
+
+        #fixed_size
     }
+    .into()
 }
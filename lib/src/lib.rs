@@ -2,9 +2,26 @@
 //!
 //! A simple binary encoding and decoding library.
 
-#![feature(maybe_uninit_array_assume_init)]
+// Lets the code generated by the derive macro name this crate's items as `::binary::...` here as
+// well as in dependent crates.
+extern crate self as binary;
 
+use std::borrow::Cow;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::cmp::{self, Reverse};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque};
+use std::convert::Infallible;
+use std::ffi::OsString;
+use std::marker::{PhantomData, PhantomPinned};
+use std::ops::{ControlFlow, RangeBounds};
+use std::path::PathBuf;
+use std::sync::atomic::{
+    AtomicBool, AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicU16, AtomicU32, AtomicU64,
+    AtomicU8, Ordering,
+};
+use std::sync::{LazyLock, Mutex, RwLock};
+use std::task::Poll;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Contains the Binary macro for deriving the Binary trait.
 #[cfg(feature = "derive")]
@@ -14,27 +31,281 @@ pub mod derive {
     pub use binary_derive::Binary;
 }
 
+/// Destinations for encoded bytes, so that values can be written somewhere other than a vector
+/// without first being buffered in one.
+pub trait ByteSink {
+    /// Appends a byte.
+    fn push(&mut self, b: u8);
+    /// Appends a slice of bytes.
+    fn write_bytes(&mut self, bs: &[u8]);
+}
+
+impl ByteSink for Vec<u8> {
+    fn push(&mut self, b: u8) {
+        Vec::push(self, b);
+    }
+
+    fn write_bytes(&mut self, bs: &[u8]) {
+        self.extend_from_slice(bs);
+    }
+}
+
+/// Writes encoded bytes to a writer. As writing to a sink can't fail, the first error is held on
+/// to and no more bytes are written afterward; [WriteSink::finish] reports it.
+pub struct WriteSink<W> {
+    w: W,
+    error: Option<std::io::Error>,
+}
+
+impl<W: std::io::Write> WriteSink<W> {
+    /// Writes to the given writer, which is usually best buffered.
+    pub fn new(w: W) -> Self {
+        WriteSink { w, error: None }
+    }
+
+    /// Returns the writer, or the first error encountered while writing to it.
+    pub fn finish(self) -> std::io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.w),
+        }
+    }
+}
+
+impl<W: std::io::Write> ByteSink for WriteSink<W> {
+    fn push(&mut self, b: u8) {
+        self.write_bytes(&[b]);
+    }
+
+    fn write_bytes(&mut self, bs: &[u8]) {
+        if self.error.is_none() {
+            if let Err(e) = self.w.write_all(bs) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+// Feeds encoded bytes to a hasher.
+struct HashSink<'a, H>(&'a mut H);
+
+impl<H: std::hash::Hasher> ByteSink for HashSink<'_, H> {
+    fn push(&mut self, b: u8) {
+        self.0.write(&[b]);
+    }
+
+    fn write_bytes(&mut self, bs: &[u8]) {
+        self.0.write(bs);
+    }
+}
+
 /// Types which can be serialized and deserialized into a binary format.
 pub trait Binary: Sized {
     /// Deserialize self from bytes, potentially leaving more input.
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])>;
-    /// Serialize self to the vector.
-    fn unparse(&self, bs: &mut Vec<u8>);
+    /// Serialize self to the vector. This appends to whatever is already in the vector, so
+    /// a single buffer can be reused to serialize many values back to back.
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S);
     /// Encodes the given object.
     fn to_bytes(&self) -> Vec<u8> {
         let mut bs = Vec::new();
         self.unparse(&mut bs);
         bs
     }
+    /// Parses like [Binary::parse], but also rejects encodings which `unparse` never writes,
+    /// such as the keys of a `BTreeMap` out of order, or the same key twice in any map or set.
+    /// Nothing that `unparse` writes is rejected. As hash maps and sets are written in no
+    /// particular order, their order isn't checked. Types whose every encoding is one that
+    /// `unparse` writes, like `bool`, which only accepts 0 and 1, needn't override this.
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse(bs)
+    }
     /// Parses from bytes, only returning Some when the input is exactly the right length.
     fn from_bytes(bs: &[u8]) -> Option<Self> {
         let (x, bs) = Self::parse(bs)?;
-        if bs.len() == 0 {
+        if bs.is_empty() {
+            Some(x)
+        } else {
+            None
+        }
+    }
+    /// Encodes the given object as lowercase hexadecimal, two digits to a byte.
+    fn to_hex(&self) -> String {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut s = String::new();
+        for b in self.to_bytes() {
+            s.push(DIGITS[(b >> 4) as usize] as char);
+            s.push(DIGITS[(b & 0xf) as usize] as char);
+        }
+        s
+    }
+    /// Parses hexadecimal written by [Binary::to_hex], in either case, only returning Some when
+    /// every digit is valid, they pair up into bytes, and the bytes are exactly the right length.
+    fn from_hex(s: &str) -> Option<Self> {
+        if !s.len().is_multiple_of(2) {
+            return None;
+        }
+        let digit = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+        let bs = s
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| Some(digit(pair[0])? << 4 | digit(pair[1])?))
+            .collect::<Option<Vec<u8>>>()?;
+        Self::from_bytes(&bs)
+    }
+    /// Deserialize self from bytes, returning the number of bytes consumed.
+    fn parse_counted(bs: &[u8]) -> Option<(Self, usize)> {
+        let (x, rest) = Self::parse(bs)?;
+        Some((x, bs.len() - rest.len()))
+    }
+    /// Parses a `Vec` of values, as written by its `unparse`. Implementations can override this
+    /// when many values can be read at once, as `u8` does to copy a byte string in one go.
+    fn parse_vec(bs: &[u8]) -> Option<(Vec<Self>, &[u8])> {
+        parse_elements(bs, Self::parse)
+    }
+    /// The number of bytes `unparse` would write. Implementations can override this when the
+    /// length can be computed without encoding the value.
+    fn encoded_len(&self) -> usize {
+        self.to_bytes().len()
+    }
+    /// Returns whether the input parses, with no bytes left over, into a value whose encoding is
+    /// just as long as the input. This is cheaper but weaker than checking that the value
+    /// re-encodes to exactly the input.
+    fn check_length_stable(bs: &[u8]) -> bool {
+        match Self::parse_counted(bs) {
+            Some((x, consumed)) => consumed == bs.len() && x.encoded_len() == consumed,
+            None => false,
+        }
+    }
+    /// Serialize self to the vector in canonical form, so that logically equal values always
+    /// produce identical bytes. This differs from `unparse` only for types whose encoding would
+    /// otherwise depend on something besides their value, such as the iteration order of a
+    /// `HashMap` or the payload of a NaN, and for the types containing them.
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unparse(bs);
+    }
+    /// Feeds the encoding of self to the hasher piece by piece, without building it in memory.
+    /// Hashers which hash a stream of bytes however it is split between calls to `write`, which
+    /// `Hasher` doesn't require, produce the same hash as writing the result of `to_bytes` to
+    /// them at once. Other hashers may not, and may hash values of different types with the same
+    /// encoding differently.
+    fn hash_encoding<H: std::hash::Hasher>(&self, h: &mut H) {
+        self.unparse(&mut HashSink(h));
+    }
+    /// Encodes self along with the hash of its encoding, under which it can be kept in a
+    /// content-addressed store.
+    #[cfg(feature = "blake3")]
+    fn to_cas_entry(&self) -> (blake3::Hash, Vec<u8>) {
+        let bs = self.to_bytes();
+        (blake3::hash(&bs), bs)
+    }
+    /// Decodes an entry of a content-addressed store, failing if the bytes don't have the given
+    /// hash.
+    #[cfg(feature = "blake3")]
+    fn verify_cas(hash: &blake3::Hash, bs: &[u8]) -> Option<Self> {
+        if blake3::hash(bs) != *hash {
+            return None;
+        }
+        Self::from_bytes(bs)
+    }
+    /// Encodes the given object in canonical form.
+    fn to_canonical_bytes(&self) -> Vec<u8> {
+        let mut bs = Vec::new();
+        self.unparse_canonical(&mut bs);
+        bs
+    }
+    /// Parses from bytes, only returning Some when the input is exactly the canonical encoding
+    /// of the result. This rejects, for instance, maps and sets with their elements out of order
+    /// or repeated.
+    fn from_canonical_bytes(bs: &[u8]) -> Option<Self> {
+        let x = Self::from_bytes(bs)?;
+        if x.to_canonical_bytes() == bs {
             Some(x)
         } else {
             None
         }
     }
+    /// Encodes the given object preceded by the `u32` length of its encoding, so that a reader
+    /// can find the end of the value without parsing it. Panics if the encoding is longer than
+    /// `u32::MAX` bytes.
+    fn to_framed_bytes(&self) -> Vec<u8> {
+        let mut bs = vec![0; 4];
+        self.unparse(&mut bs);
+        let len = u32::try_from(bs.len() - 4).expect("framed values are at most u32::MAX bytes");
+        bs[0..4].copy_from_slice(&len.to_le_bytes());
+        bs
+    }
+    /// Parses a value written by [Binary::to_framed_bytes], requiring that it occupy exactly
+    /// the framed bytes, and potentially leaving more input after the frame.
+    fn parse_framed(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (len, bs) = u32::parse(bs)?;
+        let len = len as usize;
+        if bs.len() < len {
+            return None;
+        }
+        Some((Self::from_bytes(&bs[..len])?, &bs[len..]))
+    }
+    /// Encodes the given object followed by the `u32` length of its encoding, so that a reader
+    /// starting from the end of a sequence of such records can find where the last one begins.
+    /// Panics if the encoding is longer than `u32::MAX` bytes.
+    fn to_bytes_footer(&self) -> Vec<u8> {
+        let mut bs = self.to_bytes();
+        let len = u32::try_from(bs.len()).expect("footer framed values are at most u32::MAX bytes");
+        len.unparse(&mut bs);
+        bs
+    }
+    /// Parses the value written by [Binary::to_bytes_footer] at the end of the input, requiring
+    /// that it occupy exactly the bytes before the footer, and returns the bytes preceding it.
+    fn from_bytes_footer_reverse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let footer_start = bs.len().checked_sub(4)?;
+        let len = u32::from_bytes(&bs[footer_start..])? as usize;
+        let start = footer_start.checked_sub(len)?;
+        Some((Self::from_bytes(&bs[start..footer_start])?, &bs[..start]))
+    }
+    /// Encodes the given object preceded by [SYNC_MARKER] and the length prefix of its encoding,
+    /// so that a [SyncReader] can find it again after corruption earlier in the stream.
+    fn to_bytes_synced(&self) -> Vec<u8> {
+        let payload = self.to_bytes();
+        let mut bs = Vec::with_capacity(SYNC_MARKER.len() + LEN_PREFIX.bytes() + payload.len());
+        bs.extend_from_slice(&SYNC_MARKER);
+        payload.unparse(&mut bs);
+        bs
+    }
+    /// Parses like [Binary::parse], but fails rather than nesting boxes and collections more than
+    /// `max_depth` deep, so that a hostile encoding of a recursive type can't exhaust the stack.
+    fn parse_depth_limited(bs: &[u8], max_depth: usize) -> Option<(Self, &[u8])> {
+        let outer = DEPTH_BUDGET.get();
+        let _restore = RestoreDepth(outer);
+        DEPTH_BUDGET.set(Some(outer.map_or(max_depth, |outer| outer.min(max_depth))));
+        Self::parse(bs)
+    }
+    /// Asserts that the encoding of self parses back to an equal value, panicking with the
+    /// encoding in hex otherwise. This is intended for tests and fuzzing of user types.
+    #[cfg(feature = "test-helpers")]
+    fn assert_roundtrip(&self)
+    where
+        Self: PartialEq + std::fmt::Debug,
+    {
+        let bs = self.to_bytes();
+        let parsed = Self::from_bytes(&bs);
+        if parsed.as_ref() != Some(self) {
+            let hex: String = bs.iter().map(|b| format!("{:02x}", b)).collect();
+            panic!(
+                "{:?} did not round trip: encoded as {} which parsed as {:?}",
+                self, hex, parsed
+            );
+        }
+    }
+}
+
+/// Why a value failed to parse, for the few methods which distinguish between failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryError {
+    /// The input isn't the encoding of any value.
+    Invalid,
+    /// The input begins with an enum tag reserved, via `#[binary(reserved = ...)]`, for variants
+    /// which may be added in the future.
+    ReservedTag(u8),
 }
 
 /// Parse the given number of bytes into a fixed length array. This can be helpful for writing
@@ -43,7 +314,7 @@ pub fn parse_bytes<const N: usize>(bs: &[u8]) -> Option<(&[u8; N], &[u8])> {
     if bs.len() >= N {
         Some((
             <&[u8; N] as TryFrom<&[u8]>>::try_from(&bs[0..N])
-                .expect(&format!("all length {}+ bytestrings should parse here", N)),
+                .unwrap_or_else(|_| panic!("all length {}+ bytestrings should parse here", N)),
             &bs[N..],
         ))
     } else {
@@ -51,864 +322,7168 @@ pub fn parse_bytes<const N: usize>(bs: &[u8]) -> Option<(&[u8; N], &[u8])> {
     }
 }
 
-impl<A: Binary, B: Binary> Binary for (A, B) {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (a, bs) = A::parse(bs)?;
-        let (b, bs) = B::parse(bs)?;
-        Some(((a, b), bs))
+/// Serialize the items as a length-prefixed sequence, appending to the vector. The result is
+/// the same as serializing a `Vec` of the items, and can be parsed back as one.
+pub fn encode_many<T: Binary, S: ByteSink + ?Sized>(items: &[T], bs: &mut S) {
+    unparse_len(items.len(), bs);
+    for item in items {
+        item.unparse(bs);
     }
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.0.unparse(bs);
-        self.1.unparse(bs);
+/// Serialize the entries of the map whose keys are in the range, without copying them into a
+/// map of their own. The result is the same as serializing a `BTreeMap` of just those entries,
+/// and can be parsed back as one.
+pub fn unparse_range<K: Binary + Ord, V: Binary, S: ByteSink + ?Sized>(
+    map: &BTreeMap<K, V>,
+    range: impl RangeBounds<K>,
+    bs: &mut S,
+) {
+    let entries = map.range(range);
+    unparse_len(entries.clone().count(), bs);
+    for (k, v) in entries {
+        k.unparse(bs);
+        v.unparse(bs);
     }
 }
 
-impl<A: Binary, B: Binary, C: Binary> Binary for (A, B, C) {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (a, bs) = A::parse(bs)?;
-        let (b, bs) = B::parse(bs)?;
-        let (c, bs) = C::parse(bs)?;
-        Some(((a, b, c), bs))
-    }
+/// Returns the first byte of the input without parsing anything. Enums deriving Binary encode
+/// their tag as this byte, numbering variants from zero in the order they are declared, so this
+/// can be used to decide how to parse a message before committing to any one type. This does not
+/// hold for enums with a `magic` or `version` attribute, whose header comes first, nor for enums
+/// marked `#[binary(repr_tag)]`, whose tag is their discriminant as their integer repr.
+pub fn peek_tag(bs: &[u8]) -> Option<u8> {
+    bs.first().copied()
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.0.unparse(bs);
-        self.1.unparse(bs);
-        self.2.unparse(bs);
+/// Types which can describe their fields, so that their encoding can be preceded by a description
+/// of the type it was written as. This is derived along with `Binary`.
+pub trait Schema: Binary {
+    /// A description of this type's fields in the order they are serialized.
+    const SCHEMA: &'static str;
+    /// Encodes the value preceded by its `SCHEMA`, which can be read back with [schema_of]
+    /// without knowing the type.
+    fn to_self_describing(&self) -> Vec<u8> {
+        let mut bs = Vec::new();
+        Self::SCHEMA.to_string().unparse(&mut bs);
+        self.unparse(&mut bs);
+        bs
+    }
+    /// Parses bytes written by `to_self_describing`, failing if the schema they were written with
+    /// differs from this type's.
+    fn from_self_describing(bs: &[u8]) -> Option<Self> {
+        let (schema, bs) = String::parse(bs)?;
+        if schema != Self::SCHEMA {
+            return None;
+        }
+        Self::from_bytes(bs)
     }
 }
 
-// TODO implement more tuples via a proc macro
-
-impl Binary for () {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        Some(((), bs))
+/// Structs whose fields can be found in their encoding without constructing them. This is derived
+/// along with `Binary` for structs.
+pub trait FieldOffsets: Binary {
+    /// Parses the fields without constructing the value, returning the name, offset, and length
+    /// of each one in the order they are serialized.
+    fn field_offsets(bs: &[u8]) -> Option<Vec<(&'static str, usize, usize)>>;
+    /// Parses the value, returning the name and byte range of each field in the order they are
+    /// serialized, preceded by the range of the magic number and version, if any, as well as the
+    /// input left over.
+    #[allow(clippy::type_complexity)]
+    fn describe(bs: &[u8]) -> Option<(Vec<(String, std::ops::Range<usize>)>, &[u8])> {
+        let (_, rest) = Self::parse(bs)?;
+        let end = bs.len() - rest.len();
+        let offsets = Self::field_offsets(bs)?;
+        let fields_start = offsets.first().map_or(end, |(_, offset, _)| *offset);
+        let mut ranges = Vec::new();
+        if fields_start > 0 {
+            ranges.push(("header".to_string(), 0..fields_start));
+        }
+        for (name, offset, length) in offsets {
+            ranges.push((name.to_string(), offset..offset + length));
+        }
+        Some((ranges, rest))
     }
+}
 
-    fn unparse(&self, _bs: &mut Vec<u8>) {}
+/// Reads the schema from the front of bytes written by [Schema::to_self_describing], without
+/// decoding the value which follows it.
+pub fn schema_of(bs: &[u8]) -> Option<String> {
+    let (schema, _bs) = String::parse(bs)?;
+    Some(schema)
 }
 
-impl<const LENGTH: usize, A: Binary> Binary for [A; LENGTH] {
-    fn parse(mut bs: &[u8]) -> Option<(Self, &[u8])> {
-        use std::mem::MaybeUninit;
-        let mut marray: [MaybeUninit<A>; LENGTH] = unsafe { MaybeUninit::uninit().assume_init() };
-        for i in 0..LENGTH {
-            let (x, bs_prime) = A::parse(bs)?;
-            marray[i] = MaybeUninit::new(x);
-            bs = bs_prime;
-        }
+/// The bytes beginning every frame written by [Binary::to_bytes_synced].
+pub const SYNC_MARKER: [u8; 2] = [0xA5, 0x5A];
 
-        let array = unsafe { MaybeUninit::array_assume_init::<LENGTH>(marray) };
+/// Iterates over the values in a stream of frames written by [Binary::to_bytes_synced], skipping
+/// over any garbage between them. A frame is only accepted if its payload parses exactly, but
+/// garbage which happens to look like a valid frame will still be accepted.
+pub struct SyncReader<'a, T> {
+    bs: &'a [u8],
+    _marker: std::marker::PhantomData<T>,
+}
 
-        Some((array, bs))
+impl<'a, T> SyncReader<'a, T> {
+    /// Reads frames from the given bytes.
+    pub fn new(bs: &'a [u8]) -> Self {
+        SyncReader {
+            bs,
+            _marker: std::marker::PhantomData,
+        }
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        for i in 0..LENGTH {
-            self[i].unparse(bs);
-        }
+    /// The bytes which have yet to be read.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.bs
     }
 }
 
-impl<A: Binary> Binary for Vec<A> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut v = Vec::new();
-        for _i in 0..n {
-            let (a, bs_prime) = A::parse(bs)?;
-            v.push(a);
-            bs = bs_prime;
-        }
-        Some((v, bs))
-    }
+impl<T: Binary> Iterator for SyncReader<'_, T> {
+    type Item = T;
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for a in self.iter() {
-            a.unparse(bs);
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let start = self.bs.windows(2).position(|w| w == SYNC_MARKER)?;
+            let frame = &self.bs[start + SYNC_MARKER.len()..];
+            let parsed = <Vec<u8> as Binary>::parse(frame)
+                .and_then(|(payload, rest)| Some((T::from_bytes(&payload)?, rest)));
+            match parsed {
+                Some((x, rest)) => {
+                    self.bs = rest;
+                    return Some(x);
+                }
+                // not a real frame, so resume scanning just past this marker
+                None => self.bs = &self.bs[start + 1..],
+            }
         }
     }
 }
 
-impl Binary for i128 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (i128_bytes, bs) = parse_bytes::<16>(bs)?;
-        Some((i128::from_le_bytes(i128_bytes.clone()), bs))
-    }
+/// Decodes values from input which arrives in pieces, such as from a non-blocking socket, by
+/// buffering it until a whole value is available. As an incomplete encoding can't be told apart
+/// from an invalid one, invalid input leaves the decoder waiting for more, unless it was made
+/// with [Decoder::with_limit], in which case it fails once too much input is waiting.
+///
+/// Each attempt to decode a value parses the input buffered for it from the start, so input
+/// should be pushed in chunks rather than a few bytes at a time.
+pub struct Decoder<T> {
+    buffer: Vec<u8>,
+    // where the input yet to be decoded begins in the buffer, as the input before it is only
+    // removed once it takes up half the buffer
+    start: usize,
+    // how much input was waiting when it last failed to parse, so that it isn't parsed again
+    // until more arrives
+    attempted: Option<usize>,
+    limit: usize,
+    _marker: std::marker::PhantomData<T>,
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Decoder::new()
     }
 }
 
-impl Binary for u128 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (u128_bytes, bs) = parse_bytes::<16>(bs)?;
-        Some((u128::from_le_bytes(u128_bytes.clone()), bs))
+impl<T> Decoder<T> {
+    /// A decoder with no input buffered, which waits for as much input as a value needs.
+    pub fn new() -> Self {
+        Decoder::with_limit(usize::MAX)
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
+    /// A decoder with no input buffered, which fails rather than wait for a value once more
+    /// than `max_bytes` of input are buffered without holding a whole one.
+    pub fn with_limit(max_bytes: usize) -> Self {
+        Decoder {
+            buffer: Vec::new(),
+            start: 0,
+            attempted: None,
+            limit: max_bytes,
+            _marker: std::marker::PhantomData,
+        }
     }
-}
 
-impl Binary for u64 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (u64_bytes, bs) = parse_bytes::<8>(bs)?;
-        Some((u64::from_le_bytes(u64_bytes.clone()), bs))
+    /// Adds more input after that already buffered.
+    pub fn push_bytes(&mut self, bs: &[u8]) {
+        if self.start > self.buffer.len() / 2 {
+            self.buffer.drain(..self.start);
+            self.start = 0;
+        }
+        self.buffer.extend_from_slice(bs);
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
+    /// The input which has yet to be decoded.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.start..]
     }
 }
 
-impl Binary for i64 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (i64_bytes, bs) = parse_bytes::<8>(bs)?;
-        Some((i64::from_le_bytes(i64_bytes.clone()), bs))
+impl<T: Binary> Decoder<T> {
+    /// Decodes the next value if the buffered input holds all of it, removing it from the
+    /// buffer, and otherwise returns None and waits for more input. Fails with
+    /// [BinaryError::Invalid] if more input is buffered than the limit allows without holding a
+    /// value, and keeps failing, as it can't tell where the next value would begin.
+    pub fn try_next(&mut self) -> Result<Option<T>, BinaryError> {
+        let pending = &self.buffer[self.start..];
+        if self.attempted != Some(pending.len()) {
+            if let Some((x, consumed)) = T::parse_counted(pending) {
+                self.start += consumed;
+                self.attempted = None;
+                return Ok(Some(x));
+            }
+            self.attempted = Some(pending.len());
+        }
+        if pending.len() > self.limit {
+            Err(BinaryError::Invalid)
+        } else {
+            Ok(None)
+        }
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
+    /// Decodes the next value, reading more input from the reader until the buffer holds all of
+    /// it. Each read asks for as much input as is already waiting, so that a large value is
+    /// parsed only a logarithmic number of times, and whatever is read past the value stays
+    /// buffered for the next one. Nothing beyond the limit is read, and holding that much input
+    /// without a value produces an `InvalidData` error, while running out of input produces an
+    /// `UnexpectedEof` error.
+    pub fn read_next<R: std::io::Read>(&mut self, r: &mut R) -> std::io::Result<T> {
+        use std::io::{Error, ErrorKind};
+        loop {
+            match self.try_next() {
+                Ok(Some(x)) => return Ok(x),
+                Ok(None) if self.buffered().len() < self.limit => {}
+                Ok(None) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "value exceeded the byte budget",
+                    ))
+                }
+                Err(e) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("value did not parse: {:?}", e),
+                    ))
+                }
+            }
+            if self.start > self.buffer.len() / 2 {
+                self.buffer.drain(..self.start);
+                self.start = 0;
+            }
+            let pending = self.buffered().len();
+            let want = pending.max(64).min(self.limit - pending);
+            let old_len = self.buffer.len();
+            self.buffer.resize(old_len + want, 0);
+            let read = loop {
+                match r.read(&mut self.buffer[old_len..]) {
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    read => break read,
+                }
+            };
+            let n = *read.as_ref().unwrap_or(&0);
+            self.buffer.truncate(old_len + n);
+            if read? == 0 {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "input ended before a value was parsed",
+                ));
+            }
+        }
     }
 }
 
-impl Binary for u32 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (u32_bytes, bs) = parse_bytes::<4>(bs)?;
-        Some((u32::from_le_bytes(u32_bytes.clone()), bs))
-    }
+/// Iterates over the values in a stream of records, each preceded by the `u32` length of its
+/// encoding as written by [Binary::to_framed_bytes]. Iteration ends cleanly when the stream ends
+/// between records, and otherwise ends after yielding the first error, which is `UnexpectedEof`
+/// for a truncated record and `InvalidData` for a record which doesn't parse.
+pub fn records<R: std::io::Read, T: Binary>(mut r: R) -> impl Iterator<Item = std::io::Result<T>> {
+    use std::io::{Error, ErrorKind, Read};
+    let mut done = false;
+    std::iter::from_fn(move || {
+        if done {
+            return None;
+        }
+        let mut read_record = || -> std::io::Result<Option<T>> {
+            let mut len_bytes = [0u8; 4];
+            let mut filled = 0;
+            while filled < len_bytes.len() {
+                match r.read(&mut len_bytes[filled..]) {
+                    Ok(0) if filled == 0 => return Ok(None),
+                    Ok(0) => {
+                        return Err(Error::new(
+                            ErrorKind::UnexpectedEof,
+                            "stream ended within a record length",
+                        ))
+                    }
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            let len = u32::from_le_bytes(len_bytes) as u64;
+            // reading through take grows the buffer as the bytes arrive, so a lying length
+            // can't make us allocate up front
+            let mut payload = Vec::new();
+            r.by_ref().take(len).read_to_end(&mut payload)?;
+            if (payload.len() as u64) < len {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "stream ended within a record",
+                ));
+            }
+            T::from_bytes(&payload)
+                .map(Some)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "record did not parse"))
+        };
+        let result = read_record().transpose();
+        done = !matches!(result, Some(Ok(_)));
+        result
+    })
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
-    }
+/// Splits an encoded `Vec<T>` into `n` shards, or one per element if there are fewer, so that
+/// they can be parsed independently with [parse_shard] and reassembled with [merge]. Each shard
+/// is the number of elements in a contiguous run of them, along with their encodings without any
+/// length prefix, and the runs differ in length by at most one element. Finding where the
+/// elements begin requires parsing them, which [shard_fixed] avoids. Returns None if `n` is zero
+/// or if the input isn't exactly the encoding of a `Vec<T>`.
+pub fn shard<T: Binary>(bs: &[u8], n: usize) -> Option<Vec<(usize, &[u8])>> {
+    split_shards(bs, n, |mut rest, count| {
+        for i in 0..count {
+            let (_, rest_prime) = T::parse(rest)?;
+            ensure_progress(rest, rest_prime, count - i)?;
+            rest = rest_prime;
+        }
+        Some(rest)
+    })
 }
 
-impl Binary for i32 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (i32_bytes, bs) = parse_bytes::<4>(bs)?;
-        Some((i32::from_le_bytes(i32_bytes.clone()), bs))
-    }
+/// Splits an encoded `Vec<T>` like [shard], but in constant time per shard, as every element is
+/// encoded in `T::SIZE` bytes. The elements aren't parsed, so a shard may still fail to parse.
+pub fn shard_fixed<T: Binary + FixedSize>(bs: &[u8], n: usize) -> Option<Vec<(usize, &[u8])>> {
+    split_shards(bs, n, |rest, count| {
+        if T::SIZE == 0 {
+            ensure_progress(rest, rest, count)?;
+        }
+        rest.get(count.checked_mul(T::SIZE)?..)
+    })
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes())
+// Splits the elements of an encoded `Vec` into at most `n` runs, where `skip` steps over the
+// given number of elements.
+fn split_shards<'a>(
+    bs: &'a [u8],
+    n: usize,
+    mut skip: impl FnMut(&'a [u8], usize) -> Option<&'a [u8]>,
+) -> Option<Vec<(usize, &'a [u8])>> {
+    if n == 0 {
+        return None;
+    }
+    let (len, mut rest) = parse_len(bs)?;
+    let n = n.min(len);
+    let mut shards = Vec::with_capacity(n);
+    for i in 0..n {
+        let count = len / n + usize::from(i < len % n);
+        let rest_prime = skip(rest, count)?;
+        shards.push((count, &rest[..rest.len() - rest_prime.len()]));
+        rest = rest_prime;
+    }
+    if rest.is_empty() {
+        Some(shards)
+    } else {
+        None
     }
 }
 
-impl Binary for u16 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (u16_bytes, bs) = parse_bytes::<2>(bs)?;
-        Some((u16::from_le_bytes(u16_bytes.clone()), bs))
+/// Parses the `count` elements of a shard returned by [shard] or [shard_fixed], failing if any
+/// of its bytes are left over.
+pub fn parse_shard<T: Binary>(count: usize, mut bs: &[u8]) -> Option<Vec<T>> {
+    let mut v = Vec::with_capacity(count.min(bs.len()));
+    for i in 0..count {
+        let (x, bs_prime) = T::parse(bs)?;
+        v.push(x);
+        ensure_progress(bs, bs_prime, count - i)?;
+        bs = bs_prime;
     }
-
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes());
+    if bs.is_empty() {
+        Some(v)
+    } else {
+        None
     }
 }
 
-impl Binary for i16 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (i16_bytes, bs) = parse_bytes::<2>(bs)?;
-        Some((i16::from_le_bytes(i16_bytes.clone()), bs))
-    }
+/// Reassembles the elements of parsed shards, in order, into the `Vec` they were split from.
+pub fn merge<T>(shards: Vec<Vec<T>>) -> Vec<T> {
+    shards.into_iter().flatten().collect()
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes());
-    }
+/// Types whose encoding is always `SIZE` bytes long.
+pub trait FixedSize {
+    /// The length of the encoding in bytes.
+    const SIZE: usize;
 }
 
-impl Binary for u8 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (u8_byte, bs) = parse_bytes::<1>(bs)?;
-        Some((u8::from_le_bytes(u8_byte.clone()), bs))
-    }
+macro_rules! impl_fixed_size {
+    ($($t:ty => $size:expr),* $(,)?) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = $size;
+            }
+        )*
+    };
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.push(*self);
-    }
+impl_fixed_size! {
+    () => 0,
+    PhantomPinned => 0,
+    bool => 1,
+    u8 => 1,
+    i8 => 1,
+    u16 => 2,
+    i16 => 2,
+    u32 => 4,
+    i32 => 4,
+    f32 => 4,
+    char => 4,
+    u64 => 8,
+    i64 => 8,
+    f64 => 8,
+    u128 => 16,
+    i128 => 16,
 }
-impl Binary for i8 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (i8_byte, bs) = parse_bytes::<1>(bs)?;
-        Some((i8::from_le_bytes(i8_byte.clone()), bs))
-    }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes());
-    }
+impl<T: ?Sized> FixedSize for PhantomData<T> {
+    const SIZE: usize = 0;
 }
 
-impl Binary for bool {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (b, bs) = u8::parse(bs)?;
-        if b == 1 {
-            Some((true, bs))
-        } else if b == 0 {
-            Some((false, bs))
-        } else {
-            None
-        }
-    }
+impl<A: FixedSize, const LENGTH: usize> FixedSize for [A; LENGTH] {
+    const SIZE: usize = A::SIZE * LENGTH;
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.push(if *self { 1 } else { 0 });
-    }
+impl<A: FixedSize, B: FixedSize> FixedSize for (A, B) {
+    const SIZE: usize = A::SIZE + B::SIZE;
 }
 
-impl Binary for char {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, bs) = u32::parse(bs)?;
-        Some((char::from_u32(n)?, bs))
-    }
+impl<A: FixedSize, B: FixedSize, C: FixedSize> FixedSize for (A, B, C) {
+    const SIZE: usize = A::SIZE + B::SIZE + C::SIZE;
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        u32::from(*self).unparse(bs);
-    }
+/// Types whose `Binary` encoding is always `LEN` bytes long, which are those that are also
+/// `FixedSize`, including structs deriving `Binary` marked `#[binary(fixed_size)]`.
+pub trait FixedBinary: Binary + FixedSize {
+    /// The length of the encoding in bytes.
+    const LEN: usize;
 }
 
-impl Binary for String {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (ss, bs) = <Vec<u8> as Binary>::parse(bs)?;
-        match String::from_utf8(ss) {
-            Err(_e) => None,
-            Ok(s) => Some((s, bs)),
-        }
-    }
+impl<T: Binary + FixedSize> FixedBinary for T {
+    const LEN: usize = T::SIZE;
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        bs.extend_from_slice(self.as_bytes());
-    }
+/// Types which can also be encoded with their bytes in big endian order. This is implemented for
+/// numbers, and derived for structs marked `#[binary(both_endian)]`, which can't also override
+/// their bounds with `#[binary(bound = "...")]`, as those bounds would be for `Binary`:
+///
+/// ```compile_fail
+/// use binary::Binary;
+///
+/// #[derive(Binary)]
+/// #[binary(both_endian, bound = "T: Binary")]
+/// struct Pair<T> {
+///     a: T,
+///     b: T,
+/// }
+/// ```
+pub trait BigEndian: Binary {
+    /// Deserialize self from big endian bytes, potentially leaving more input.
+    fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])>;
+    /// Serialize self to the sink in big endian order.
+    fn unparse_be<S: ByteSink + ?Sized>(&self, bs: &mut S);
 }
 
-impl Binary for f32 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (f32_bytes, bs) = parse_bytes::<4>(bs)?;
-        Some((f32::from_le_bytes(f32_bytes.clone()), bs))
+macro_rules! impl_big_endian {
+    ($($t:ty => $size:literal),* $(,)?) => {
+        $(
+            impl BigEndian for $t {
+                fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    let (t_bytes, bs) = parse_bytes::<$size>(bs)?;
+                    Some((<$t>::from_be_bytes(*t_bytes), bs))
+                }
+
+                fn unparse_be<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                    bs.write_bytes(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_big_endian! {
+    u8 => 1,
+    i8 => 1,
+    u16 => 2,
+    i16 => 2,
+    u32 => 4,
+    i32 => 4,
+    f32 => 4,
+    u64 => 8,
+    i64 => 8,
+    f64 => 8,
+    u128 => 16,
+    i128 => 16,
+}
+
+impl BigEndian for bool {
+    fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])> {
+        bool::parse(bs)
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes());
+    fn unparse_be<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unparse(bs);
     }
 }
 
-impl Binary for f64 {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (f64_bytes, bs) = parse_bytes::<8>(bs)?;
-        Some((f64::from_le_bytes(f64_bytes.clone()), bs))
+impl<const LENGTH: usize, A: BigEndian> BigEndian for [A; LENGTH] {
+    fn parse_be(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_array(bs, A::parse_be)
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_le_bytes());
+    fn unparse_be<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        for a in self {
+            a.unparse_be(bs);
+        }
     }
 }
 
-impl<Key: Binary + std::hash::Hash + Eq, Value: Binary> Binary for HashMap<Key, Value> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = HashMap::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            let (v, bs_prime) = Value::parse(bs_prime)?;
-            m.insert(k, v);
-            bs = bs_prime;
-        }
-        Some((m, bs))
+/// Lazily decodes the elements of an encoded `Vec<T>` without allocating. Returns None unless
+/// the input holds exactly as many elements as its length prefix claims, or if the elements are
+/// encoded as zero bytes, since their number can't be checked. The elements are
+/// parsed as they are iterated over, and iteration ends early at the first one which doesn't
+/// parse, such as a `bool` which isn't 0 or 1.
+pub fn iter_fixed<'a, T: Binary + FixedSize + 'a>(
+    bs: &'a [u8],
+) -> Option<impl Iterator<Item = T> + 'a> {
+    let (n, bs) = parse_len(bs)?;
+    if T::SIZE == 0 || n.checked_mul(T::SIZE)? != bs.len() {
+        return None;
     }
+    Some(bs.chunks_exact(T::SIZE).map_while(T::from_bytes))
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for (k, v) in self {
-            k.unparse(bs);
-            v.unparse(bs);
-        }
+/// Maps the function over the elements of an encoded `Vec<T>`, producing the encoding of the
+/// resulting `Vec<U>` without collecting either vector. Returns None if [iter_fixed] would, or
+/// if any element fails to parse.
+pub fn map_encoded<T: Binary + FixedSize, U: Binary, F: FnMut(T) -> U>(
+    bs: &[u8],
+    mut f: F,
+) -> Option<Vec<u8>> {
+    let (n, elements) = parse_len(bs)?;
+    if T::SIZE == 0 || n.checked_mul(T::SIZE)? != elements.len() {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bs.len());
+    unparse_len(n, &mut out);
+    for element in elements.chunks_exact(T::SIZE) {
+        f(T::from_bytes(element)?).unparse(&mut out);
     }
+    Some(out)
 }
 
-impl<Key: Binary + Ord, Value: Binary> Binary for BTreeMap<Key, Value> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = BTreeMap::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            let (v, bs_prime) = Value::parse(bs_prime)?;
-            m.insert(k, v);
-            bs = bs_prime;
+/// The width of the length prefix of a collection encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrefixWidth {
+    U8,
+    U16,
+    U32,
+    U64,
+}
+
+impl PrefixWidth {
+    const fn bytes(self) -> usize {
+        match self {
+            PrefixWidth::U8 => 1,
+            PrefixWidth::U16 => 2,
+            PrefixWidth::U32 => 4,
+            PrefixWidth::U64 => 8,
         }
-        Some((m, bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for (k, v) in self {
-            k.unparse(bs);
-            v.unparse(bs);
+    fn parse_len(self, bs: &[u8]) -> Option<(u64, &[u8])> {
+        match self {
+            PrefixWidth::U8 => u8::parse(bs).map(|(n, bs)| (n as u64, bs)),
+            PrefixWidth::U16 => u16::parse(bs).map(|(n, bs)| (n as u64, bs)),
+            PrefixWidth::U32 => u32::parse(bs).map(|(n, bs)| (n as u64, bs)),
+            PrefixWidth::U64 => u64::parse(bs),
         }
     }
-}
 
-impl<Key: Binary + std::hash::Hash + Eq> Binary for HashSet<Key> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = HashSet::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            m.insert(k);
-            bs = bs_prime;
+    fn unparse_len<S: ByteSink + ?Sized>(self, n: u64, bs: &mut S) -> Option<()> {
+        match self {
+            PrefixWidth::U8 => u8::try_from(n).ok()?.unparse(bs),
+            PrefixWidth::U16 => u16::try_from(n).ok()?.unparse(bs),
+            PrefixWidth::U32 => u32::try_from(n).ok()?.unparse(bs),
+            PrefixWidth::U64 => n.unparse(bs),
         }
-        Some((m, bs))
+        Some(())
     }
+}
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for k in self {
-            k.unparse(bs);
+// Collections are prefixed by their length, which is a `u64` unless the `len32` feature is
+// enabled, in which case it is a `u32`.
+const LEN_PREFIX: PrefixWidth = if cfg!(feature = "len32") {
+    PrefixWidth::U32
+} else {
+    PrefixWidth::U64
+};
+
+thread_local! {
+    // How many more collections or boxes may be nested within one another while parsing, if
+    // limited by `Binary::parse_depth_limited`.
+    static DEPTH_BUDGET: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Puts the depth budget back as it was when dropped, so that it is restored even if a parser
+// panics and the panic is caught.
+struct RestoreDepth(Option<usize>);
+
+impl Drop for RestoreDepth {
+    fn drop(&mut self) {
+        DEPTH_BUDGET.set(self.0);
+    }
+}
+
+// Runs the parser one level deeper, failing if that exceeds the depth budget. Every type which
+// a recursive type can be built from, namely boxes and collections, parses through this, so
+// that the depth of the values parsed is bounded.
+fn nested<T>(parse: impl FnOnce() -> Option<T>) -> Option<T> {
+    match DEPTH_BUDGET.get() {
+        None => parse(),
+        Some(0) => None,
+        Some(budget) => {
+            let _restore = RestoreDepth(Some(budget));
+            DEPTH_BUDGET.set(Some(budget - 1));
+            parse()
         }
     }
 }
 
-impl<Key: Binary + Ord> Binary for BTreeSet<Key> {
+// The most elements a collection may have which are encoded as nothing. Parsing depends only on
+// the input, so once an element consumes none of it, neither does any element after it, and
+// without a limit a huge length would take nearly forever to parse.
+const MAX_EMPTY_ELEMENTS: usize = 1 << 16;
+
+// Fails if the element parsed from `before`, leaving `after`, consumed no input while more than
+// `MAX_EMPTY_ELEMENTS` elements, counting it, are left to parse.
+fn ensure_progress(before: &[u8], after: &[u8], remaining: usize) -> Option<()> {
+    (after.len() < before.len() || remaining <= MAX_EMPTY_ELEMENTS).then_some(())
+}
+
+// Fails on lengths which don't fit in a `usize`, as such collections couldn't fit in memory.
+fn parse_len(bs: &[u8]) -> Option<(usize, &[u8])> {
+    let (n, bs) = LEN_PREFIX.parse_len(bs)?;
+    Some((usize::try_from(n).ok()?, bs))
+}
+
+// Panics if the length doesn't fit in the prefix, rather than writing a truncated length.
+fn unparse_len<S: ByteSink + ?Sized>(n: usize, bs: &mut S) {
+    LEN_PREFIX.unparse_len(n as u64, bs).unwrap_or_else(|| {
+        panic!(
+            "length {} does not fit in a {:?} length prefix",
+            n, LEN_PREFIX
+        )
+    })
+}
+
+/// Rewrites the length prefix of an encoded collection from one width to another, leaving the
+/// encoded elements untouched. Only the outermost prefix is rewritten, so the elements of nested
+/// collections keep their prefixes. Returns None if the input is too short to hold a prefix or if
+/// the length doesn't fit in the target width.
+pub fn reframe_prefix(bs: &[u8], from: PrefixWidth, to: PrefixWidth) -> Option<Vec<u8>> {
+    let (n, rest) = from.parse_len(bs)?;
+    let mut out = Vec::with_capacity(8 + rest.len());
+    to.unparse_len(n, &mut out)?;
+    out.extend_from_slice(rest);
+    Some(out)
+}
+
+impl<A: Binary, B: Binary> Binary for (A, B) {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = BTreeSet::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            m.insert(k);
-            bs = bs_prime;
-        }
-        Some((m, bs))
+        let (a, bs) = A::parse(bs)?;
+        let (b, bs) = B::parse(bs)?;
+        Some(((a, b), bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for k in self {
-            k.unparse(bs);
-        }
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        let (b, bs) = B::parse_strict(bs)?;
+        Some(((a, b), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+        self.1.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+        self.1.unparse_canonical(bs);
     }
 }
 
-impl<Key: Binary + Ord> Binary for BinaryHeap<Key> {
+impl<A: Binary, B: Binary, C: Binary> Binary for (A, B, C) {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = BinaryHeap::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            m.push(k);
-            bs = bs_prime;
-        }
-        Some((m, bs))
+        let (a, bs) = A::parse(bs)?;
+        let (b, bs) = B::parse(bs)?;
+        let (c, bs) = C::parse(bs)?;
+        Some(((a, b, c), bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for k in self {
-            k.unparse(bs);
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        let (b, bs) = B::parse_strict(bs)?;
+        let (c, bs) = C::parse_strict(bs)?;
+        Some(((a, b, c), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+        self.1.unparse(bs);
+        self.2.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+        self.1.unparse_canonical(bs);
+        self.2.unparse_canonical(bs);
+    }
+}
+
+/// Types which can be serialized, exactly as an owned type implementing [Binary] is, but not
+/// parsed, such as references, so that borrowed data like `(&A, &B)` can be serialized without
+/// cloning it. As a reference can't be conjured from bytes, there is no `parse` to call by
+/// mistake; parse the owned type instead, or borrow from the input with [BinaryBorrow].
+///
+/// ```compile_fail
+/// use binary::Binary;
+///
+/// let _ = <&u32 as Binary>::from_bytes(&[0; 4]);
+/// ```
+pub trait Encode {
+    /// Serialize self to the sink, as [Binary::unparse] does.
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S);
+    /// Serialize self to the sink in canonical form, as [Binary::unparse_canonical] does.
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unparse(bs);
+    }
+    /// Encodes the given object.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bs = Vec::new();
+        self.unparse(&mut bs);
+        bs
+    }
+}
+
+// References encode exactly as their referents.
+impl<A: Binary> Encode for &A {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        (**self).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        (**self).unparse_canonical(bs);
+    }
+}
+
+// String slices encode exactly as a `String`.
+impl Encode for &str {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        bs.write_bytes(self.as_bytes());
+    }
+}
+
+// Slices encode exactly as a `Vec` of their elements.
+impl<A: Binary> Encode for &[A] {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        encode_many(self, bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in *self {
+            a.unparse_canonical(bs);
         }
     }
 }
 
-impl<Key: Binary> Binary for VecDeque<Key> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = VecDeque::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            m.push_back(k);
-            bs = bs_prime;
+// Options, vectors and tuples of borrowed data encode exactly as those of the owned data.
+impl<A: Encode> Encode for Option<A> {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.is_some().unparse(bs);
+        if let Some(a) = self {
+            a.unparse(bs);
         }
-        Some((m, bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for k in self {
-            k.unparse(bs);
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.is_some().unparse(bs);
+        if let Some(a) = self {
+            a.unparse_canonical(bs);
         }
     }
 }
 
-impl<Key: Binary> Binary for LinkedList<Key> {
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (n, mut bs) = u64::parse(bs)?;
-        let mut m = LinkedList::new();
-        for _i in 0..n {
-            let (k, bs_prime) = Key::parse(bs)?;
-            m.push_back(k);
-            bs = bs_prime;
+impl<A: Encode> Encode for Vec<A> {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse(bs);
         }
-        Some((m, bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        (self.len() as u64).unparse(bs);
-        for k in self {
-            k.unparse(bs);
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse_canonical(bs);
         }
     }
 }
 
-#[cfg(feature = "bls12_381")]
-use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
-
-#[cfg(feature = "bls12_381")]
-impl Binary for Scalar {
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_bytes());
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+        self.1.unparse(bs);
     }
 
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (scalar_bytes, bs) = parse_bytes::<32>(bs)?;
-        let scalar = Option::from(Scalar::from_bytes(scalar_bytes))?;
-        Some((scalar, bs))
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+        self.1.unparse_canonical(bs);
     }
 }
 
-#[cfg(feature = "bls12_381")]
-impl Binary for G1Affine {
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_compressed());
+impl<A: Encode, B: Encode, C: Encode> Encode for (A, B, C) {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+        self.1.unparse(bs);
+        self.2.unparse(bs);
     }
 
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (g1affine_bytes, bs) = parse_bytes::<48>(bs)?;
-        let g1affine = Option::from(G1Affine::from_compressed(g1affine_bytes))?;
-        Some((g1affine, bs))
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+        self.1.unparse_canonical(bs);
+        self.2.unparse_canonical(bs);
     }
 }
 
-#[cfg(feature = "bls12_381")]
-impl Binary for G1Projective {
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&G1Affine::from(self).to_compressed());
+/// Types which can be parsed without copying, borrowing string and byte slices from the input.
+/// This is derived for structs deriving `Binary` marked `#[binary(borrow)]`, which are written
+/// with [Encode], as they can't be parsed without borrowing.
+pub trait BinaryBorrow<'de>: Encode + Sized {
+    /// Deserialize self, borrowing from the input, potentially leaving more input.
+    fn parse_borrowed(bs: &'de [u8]) -> Option<(Self, &'de [u8])>;
+    /// Deserialize self, borrowing from the input, failing if there is input left over.
+    fn from_bytes_borrowed(bs: &'de [u8]) -> Option<Self> {
+        let (x, bs) = Self::parse_borrowed(bs)?;
+        if bs.is_empty() {
+            Some(x)
+        } else {
+            None
+        }
     }
+}
 
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (g1affine_bytes, bs) = parse_bytes::<48>(bs)?;
-        let g1projective = Option::from(G1Affine::from_compressed(g1affine_bytes))
-            .map(|x: G1Affine| G1Projective::from(x))?;
-        Some((g1projective, bs))
+impl<'de> BinaryBorrow<'de> for &'de str {
+    fn parse_borrowed(bs: &'de [u8]) -> Option<(Self, &'de [u8])> {
+        let (s, bs) = <&[u8]>::parse_borrowed(bs)?;
+        Some((std::str::from_utf8(s).ok()?, bs))
     }
 }
 
-#[cfg(feature = "bls12_381")]
-impl Binary for G2Affine {
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&self.to_compressed());
+impl<'de> BinaryBorrow<'de> for &'de [u8] {
+    fn parse_borrowed(bs: &'de [u8]) -> Option<(Self, &'de [u8])> {
+        let (n, bs) = parse_len(bs)?;
+        bs.split_at_checked(n)
     }
+}
 
-    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (g2affine_bytes, bs) = parse_bytes::<96>(bs)?;
-        let g2affine = Option::from(G2Affine::from_compressed(g2affine_bytes))?;
-        Some((g2affine, bs))
+impl<'de, A: BinaryBorrow<'de>> BinaryBorrow<'de> for Option<A> {
+    fn parse_borrowed(bs: &'de [u8]) -> Option<(Self, &'de [u8])> {
+        let (present, bs) = bool::parse(bs)?;
+        if present {
+            let (a, bs) = A::parse_borrowed(bs)?;
+            Some((Some(a), bs))
+        } else {
+            Some((None, bs))
+        }
     }
 }
 
-#[cfg(feature = "bls12_381")]
-impl Binary for G2Projective {
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        bs.extend_from_slice(&G2Affine::from(self).to_compressed());
+impl<'de, A: BinaryBorrow<'de>> BinaryBorrow<'de> for Vec<A> {
+    fn parse_borrowed(bs: &'de [u8]) -> Option<(Self, &'de [u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut v = Vec::new();
+            for i in 0..n {
+                let (a, bs_prime) = A::parse_borrowed(bs)?;
+                v.push(a);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((v, bs))
+        })
     }
+}
 
+// TODO implement more tuples via a proc macro
+
+// Units are encoded as nothing, so fields of unit type add no bytes and a `Vec<()>` is only its
+// length. Parsing never allocates more than `MAX_EMPTY_ELEMENTS` of them.
+impl Binary for () {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (g2affine_bytes, bs) = parse_bytes::<96>(bs)?;
-        let g2projective = Option::from(G2Affine::from_compressed(g2affine_bytes))
-            .map(|x: G2Affine| G2Projective::from(x))?;
-        Some((g2projective, bs))
+        Some(((), bs))
     }
-}
 
-#[cfg(feature = "curve25519-dalek")]
-use curve25519_dalek::{
-    ristretto::{CompressedRistretto, RistrettoPoint},
-    scalar::Scalar as RistrettoScalar,
-};
+    fn unparse<S: ByteSink + ?Sized>(&self, _bs: &mut S) {}
+}
 
-#[cfg(feature = "curve25519-dalek")]
-impl Binary for CompressedRistretto {
+// Markers hold no data, so like `()` they are encoded as nothing.
+impl<T: ?Sized> Binary for PhantomData<T> {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (compressed_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
-        Some((CompressedRistretto::from_slice(&compressed_bytes).ok()?, bs))
+        Some((PhantomData, bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.as_bytes().unparse(bs);
-    }
+    fn unparse<S: ByteSink + ?Sized>(&self, _bs: &mut S) {}
 }
 
-#[cfg(feature = "curve25519-dalek")]
-impl Binary for RistrettoPoint {
+impl Binary for PhantomPinned {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (cr, bs) = CompressedRistretto::parse(bs)?;
-        Some((CompressedRistretto::decompress(&cr)?, bs))
+        Some((PhantomPinned, bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.compress().unparse(bs);
+    fn unparse<S: ByteSink + ?Sized>(&self, _bs: &mut S) {}
+}
+
+// No value of an uninhabited type can be encoded, so none can be parsed either.
+impl Binary for Infallible {
+    fn parse(_bs: &[u8]) -> Option<(Self, &[u8])> {
+        None
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, _bs: &mut S) {
+        match *self {}
     }
 }
 
-#[cfg(feature = "curve25519-dalek")]
-impl Binary for RistrettoScalar {
+impl<A: Binary> Binary for Reverse<A> {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (scalar_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
-        Some((
-            Option::from(RistrettoScalar::from_canonical_bytes(scalar_bytes))?,
-            bs,
-        ))
+        let (a, bs) = A::parse(bs)?;
+        Some((Reverse(a), bs))
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.as_bytes().unparse(bs);
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        Some((Reverse(a), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
     }
 }
 
-#[cfg(feature = "blake3")]
-impl Binary for blake3::Hash {
+impl Binary for cmp::Ordering {
     fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
-        let (hash_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
-        Some((blake3::Hash::from_bytes(hash_bytes), bs))
+        let (b, bs) = u8::parse(bs)?;
+        match b {
+            0 => Some((cmp::Ordering::Less, bs)),
+            1 => Some((cmp::Ordering::Equal, bs)),
+            2 => Some((cmp::Ordering::Greater, bs)),
+            _ => None,
+        }
     }
 
-    fn unparse(&self, bs: &mut Vec<u8>) {
-        self.as_bytes().unparse(bs);
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.push(match self {
+            cmp::Ordering::Less => 0,
+            cmp::Ordering::Equal => 1,
+            cmp::Ordering::Greater => 2,
+        });
     }
 }
 
-#[cfg(test)]
-mod test {
-    use super::{derive, parse_bytes, Binary};
+// A function parsing a value, such as `Binary::parse`.
+type Parser<A> = fn(&[u8]) -> Option<(A, &[u8])>;
 
-    use std::collections::{
-        BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque,
-    };
+// Parses the elements of an array with the given parser.
+fn parse_array<const LENGTH: usize, A>(
+    bs: &[u8],
+    parse: Parser<A>,
+) -> Option<([A; LENGTH], &[u8])> {
+    // the array is built in place rather than collected on the heap. Once an element fails to
+    // parse the rest of the input is gone, so no more are parsed, and those before it are dropped
+    // along with the array.
+    let mut rest = Some(bs);
+    let elements = [(); LENGTH].map(|()| {
+        let (x, rest_prime) = parse(rest.take()?)?;
+        rest = Some(rest_prime);
+        Some(x)
+    });
+    let rest = rest?;
+    Some((elements.map(Option::unwrap), rest))
+}
 
-    use rand::{
-        distributions::{Alphanumeric, Distribution, Standard},
-        thread_rng,
-    };
+impl<const LENGTH: usize, A: Binary> Binary for [A; LENGTH] {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_array(bs, A::parse)
+    }
 
-    #[cfg(feature = "bls12_381")]
-    use bls12_381::Scalar;
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_array(bs, A::parse_strict)
+    }
 
-    fn test_serialization<T>(samples: usize)
-    where
-        Standard: Distribution<T>,
-        T: Binary + PartialEq + std::fmt::Debug,
-    {
-        for _i in 0..samples {
-            let x = rand::random::<T>();
-            assert_eq!(x, T::from_bytes(&x.to_bytes()).unwrap());
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        for a in self.iter() {
+            a.unparse(bs);
         }
     }
 
-    #[test]
-    fn test_primitives_binary() {
-        let samples = 10000;
-        test_serialization::<u8>(samples);
-        test_serialization::<u16>(samples);
-        test_serialization::<u32>(samples);
-        test_serialization::<u64>(samples);
-        test_serialization::<u128>(samples);
-        test_serialization::<i8>(samples);
-        test_serialization::<i16>(samples);
-        test_serialization::<i32>(samples);
-        test_serialization::<i64>(samples);
-        test_serialization::<i128>(samples);
-        test_serialization::<f32>(samples);
-        test_serialization::<f64>(samples);
-        test_serialization::<bool>(samples);
-        test_serialization::<char>(samples);
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        for a in self.iter() {
+            a.unparse_canonical(bs);
+        }
     }
+}
 
-    #[test]
-    fn test_string_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
+// Parses the length prefixed elements of a collection with the given parser.
+fn parse_elements<A>(bs: &[u8], parse: Parser<A>) -> Option<(Vec<A>, &[u8])> {
+    let (n, mut bs) = parse_len(bs)?;
+    let mut v = Vec::new();
+    for i in 0..n {
+        let (a, bs_prime) = parse(bs)?;
+        v.push(a);
+        ensure_progress(bs, bs_prime, n - i)?;
+        bs = bs_prime;
+    }
+    Some((v, bs))
+}
+
+impl<A: Binary> Binary for Vec<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| A::parse_vec(bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| parse_elements(bs, A::parse_strict))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self.iter() {
+            a.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+// Optional values are encoded as a byte which is 1 if there is a value, followed by the value.
+impl<A: Binary> Binary for Option<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (present, bs) = bool::parse(bs)?;
+        if present {
+            let (a, bs) = A::parse(bs)?;
+            Some((Some(a), bs))
+        } else {
+            Some((None, bs))
+        }
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (present, bs) = bool::parse(bs)?;
+        if present {
+            let (a, bs) = A::parse_strict(bs)?;
+            Some((Some(a), bs))
+        } else {
+            Some((None, bs))
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.is_some().unparse(bs);
+        if let Some(a) = self {
+            a.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.is_some().unparse(bs);
+        if let Some(a) = self {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+// Optional nonzero integers are encoded as the integer, with zero meaning `None`, just as they
+// are laid out in memory. The nonzero integers themselves don't implement `Binary`, as `Option`
+// of them would then have two implementations.
+macro_rules! impl_option_nonzero {
+    ($($nonzero:ty => $int:ty),* $(,)?) => {
+        $(
+            impl Binary for Option<$nonzero> {
+                fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    let (n, bs) = <$int>::parse(bs)?;
+                    Some((<$nonzero>::new(n), bs))
+                }
+
+                fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                    self.map_or(0, <$nonzero>::get).unparse(bs);
+                }
+            }
+
+            impl FixedSize for Option<$nonzero> {
+                const SIZE: usize = <$int as FixedSize>::SIZE;
+            }
+        )*
+    };
+}
+
+impl_option_nonzero! {
+    std::num::NonZeroU8 => u8,
+    std::num::NonZeroI8 => i8,
+    std::num::NonZeroU16 => u16,
+    std::num::NonZeroI16 => i16,
+    std::num::NonZeroU32 => u32,
+    std::num::NonZeroI32 => i32,
+    std::num::NonZeroU64 => u64,
+    std::num::NonZeroI64 => i64,
+    std::num::NonZeroU128 => u128,
+    std::num::NonZeroI128 => i128,
+}
+
+// Encoded as a byte which is 0 for `Continue` and 1 for `Break`, followed by the value.
+impl<B: Binary, C: Binary> Binary for ControlFlow<B, C> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (tag, bs) = u8::parse(bs)?;
+        match tag {
+            0 => C::parse(bs).map(|(c, bs)| (ControlFlow::Continue(c), bs)),
+            1 => B::parse(bs).map(|(b, bs)| (ControlFlow::Break(b), bs)),
+            _ => None,
+        }
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (tag, bs) = u8::parse(bs)?;
+        match tag {
+            0 => C::parse_strict(bs).map(|(c, bs)| (ControlFlow::Continue(c), bs)),
+            1 => B::parse_strict(bs).map(|(b, bs)| (ControlFlow::Break(b), bs)),
+            _ => None,
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self {
+            ControlFlow::Continue(c) => {
+                bs.push(0);
+                c.unparse(bs);
+            }
+            ControlFlow::Break(b) => {
+                bs.push(1);
+                b.unparse(bs);
+            }
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self {
+            ControlFlow::Continue(c) => {
+                bs.push(0);
+                c.unparse_canonical(bs);
+            }
+            ControlFlow::Break(b) => {
+                bs.push(1);
+                b.unparse_canonical(bs);
+            }
+        }
+    }
+}
+
+// Encoded as a byte which is 0 for `Pending` and 1 for `Ready`, followed by the value.
+impl<T: Binary> Binary for Poll<T> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (tag, bs) = u8::parse(bs)?;
+        match tag {
+            0 => Some((Poll::Pending, bs)),
+            1 => T::parse(bs).map(|(t, bs)| (Poll::Ready(t), bs)),
+            _ => None,
+        }
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (tag, bs) = u8::parse(bs)?;
+        match tag {
+            0 => Some((Poll::Pending, bs)),
+            1 => T::parse_strict(bs).map(|(t, bs)| (Poll::Ready(t), bs)),
+            _ => None,
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self {
+            Poll::Pending => bs.push(0),
+            Poll::Ready(t) => {
+                bs.push(1);
+                t.unparse(bs);
+            }
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self {
+            Poll::Pending => bs.push(0),
+            Poll::Ready(t) => {
+                bs.push(1);
+                t.unparse_canonical(bs);
+            }
+        }
+    }
+}
+
+impl<A: Binary> Binary for Box<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (a, bs) = A::parse(bs)?;
+            Some((Box::new(a), bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (a, bs) = A::parse_strict(bs)?;
+            Some((Box::new(a), bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        (**self).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        (**self).unparse_canonical(bs);
+    }
+}
+
+/// Slices which may be borrowed are encoded exactly like a `Vec`, and always parse as owned.
+impl<A: Binary + Clone> Binary for Cow<'_, [A]> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse(bs)?;
+        Some((Cow::Owned(v), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse_strict(bs)?;
+        Some((Cow::Owned(v), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LengthPrefixed(self).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LengthPrefixed(self).unparse_canonical(bs);
+    }
+}
+
+// Boxed slices and strings are encoded like the `Vec` and `String` they can be converted from.
+impl<A: Binary> Binary for Box<[A]> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse(bs)?;
+        Some((v.into_boxed_slice(), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse_strict(bs)?;
+        Some((v.into_boxed_slice(), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LengthPrefixed(self).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LengthPrefixed(self).unparse_canonical(bs);
+    }
+}
+
+impl Binary for Box<str> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (s, bs) = String::parse(bs)?;
+        Some((s.into_boxed_str(), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        bs.write_bytes(self.as_bytes());
+    }
+}
+
+/// A `Vec` holding at most `MAX` elements, which is encoded exactly like a `Vec` but refuses to
+/// parse a length greater than `MAX`. This protects against hostile length prefixes causing
+/// unbounded allocation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BoundedVec<A, const MAX: usize>(Vec<A>);
+
+impl<A, const MAX: usize> BoundedVec<A, MAX> {
+    /// Wraps the vector, returning None if it is longer than `MAX`.
+    pub fn new(v: Vec<A>) -> Option<Self> {
+        if v.len() <= MAX {
+            Some(BoundedVec(v))
+        } else {
+            None
+        }
+    }
+
+    /// Unwraps the underlying vector.
+    pub fn into_inner(self) -> Vec<A> {
+        self.0
+    }
+}
+
+impl<A, const MAX: usize> std::ops::Deref for BoundedVec<A, MAX> {
+    type Target = [A];
+
+    fn deref(&self) -> &[A] {
+        &self.0
+    }
+}
+
+impl<A: Binary, const MAX: usize> BoundedVec<A, MAX> {
+    fn parse_with(bs: &[u8], parse: Parser<A>) -> Option<(Self, &[u8])> {
+        let (n, mut bs) = parse_len(bs)?;
+        if n > MAX {
+            return None;
+        }
+        // the bound may be far more than the input could hold, so it limits the reservation too
+        let mut v = Vec::with_capacity(n.min(bs.len()));
+        for i in 0..n {
+            let (a, bs_prime) = parse(bs)?;
+            v.push(a);
+            ensure_progress(bs, bs_prime, n - i)?;
+            bs = bs_prime;
+        }
+        Some((BoundedVec(v), bs))
+    }
+}
+
+impl<A: Binary, const MAX: usize> Binary for BoundedVec<A, MAX> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, A::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, A::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+    }
+}
+
+/// A borrowed slice, encoded exactly like a `Vec` of its elements, so that a slice can be
+/// serialized without first collecting it into a `Vec`. Like a reference, it can only be written,
+/// with [Encode]; parse a [LengthPrefixedOwned] or a `Vec` instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LengthPrefixed<'a, T>(pub &'a [T]);
+
+impl<'a, T: Binary> Encode for LengthPrefixed<'a, T> {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        encode_many(self.0, bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        for a in self.0 {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+/// The owned counterpart of [LengthPrefixed], which parses what it writes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LengthPrefixedOwned<T>(pub Vec<T>);
+
+impl<T: Binary> Binary for LengthPrefixedOwned<T> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse(bs)?;
+        Some((LengthPrefixedOwned(v), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (v, bs) = Vec::parse_strict(bs)?;
+        Some((LengthPrefixedOwned(v), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse_canonical(bs);
+    }
+}
+
+/// Nested vectors of varying lengths, encoded as the number of inner vectors, the `u32` offset in
+/// the flattened elements at which each inner vector ends, and then the flattened elements. The
+/// offsets take half the space of the default `u64` length prefixes of a `Vec<Vec<A>>`. Encoding
+/// panics if there are more than `u32::MAX` elements in total.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ragged<A>(pub Vec<Vec<A>>);
+
+impl<A> Ragged<A> {
+    fn unparse_offsets<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        let mut end = 0u32;
+        for row in self.0.iter() {
+            end = u32::try_from(row.len())
+                .ok()
+                .and_then(|len| end.checked_add(len))
+                .expect("ragged vectors can hold at most u32::MAX elements");
+            end.unparse(bs);
+        }
+    }
+}
+
+impl<A: Binary> Ragged<A> {
+    fn parse_with(bs: &[u8], parse: Parser<A>) -> Option<(Self, &[u8])> {
+        let (n, mut bs) = parse_len(bs)?;
+        let mut ends = Vec::with_capacity(n.min(bs.len() / 4));
+        let mut previous = 0;
+        for _i in 0..n {
+            let (end, bs_prime) = u32::parse(bs)?;
+            if end < previous {
+                return None;
+            }
+            ends.push(end);
+            previous = end;
+            bs = bs_prime;
+        }
+        let mut rows = Vec::with_capacity(n.min(bs.len()));
+        let mut start = 0;
+        for end in ends {
+            let mut row = Vec::with_capacity(((end - start) as usize).min(bs.len()));
+            for i in start..end {
+                let (a, bs_prime) = parse(bs)?;
+                ensure_progress(bs, bs_prime, (end - i) as usize)?;
+                row.push(a);
+                bs = bs_prime;
+            }
+            rows.push(row);
+            start = end;
+        }
+        Some((Ragged(rows), bs))
+    }
+}
+
+impl<A: Binary> Binary for Ragged<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, A::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, A::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unparse_offsets(bs);
+        for a in self.0.iter().flatten() {
+            a.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unparse_offsets(bs);
+        for a in self.0.iter().flatten() {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+// LEB128: seven bits at a time, least significant first, with the high bit set on every byte but
+// the last. Parsing rejects encodings with redundant trailing zero bytes or more than 64 bits.
+fn unparse_varint<S: ByteSink + ?Sized>(mut n: u64, bs: &mut S) {
+    while n >= 0x80 {
+        bs.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    bs.push(n as u8);
+}
+
+fn parse_varint(bs: &[u8]) -> Option<(u64, &[u8])> {
+    let mut n = 0u64;
+    for (i, b) in bs.iter().enumerate().take(10) {
+        let bits = (*b & 0x7F) as u64;
+        if i == 9 && bits > 1 {
+            return None;
+        }
+        n |= bits << (7 * i);
+        if b & 0x80 == 0 {
+            if i > 0 && *b == 0 {
+                return None;
+            }
+            return Some((n, &bs[i + 1..]));
+        }
+    }
+    None
+}
+
+/// A `u64` encoded as a variable length integer, from one byte for values below 128 up to ten.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarU64(pub u64);
+
+impl Binary for VarU64 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_varint(bs)?;
+        Some((VarU64(n), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_varint(self.0, bs);
+    }
+}
+
+/// An `i64` zigzag encoded as a variable length integer, so that values of small magnitude are
+/// short whatever their sign.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VarI64(pub i64);
+
+impl Binary for VarI64 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_varint(bs)?;
+        Some((VarI64(unzigzag(n)), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_varint(zigzag(self.0), bs);
+    }
+}
+
+/// A map with `u64` keys, encoded as the first key followed by the difference between each key
+/// and the one before it as a variable length integer, which is much more compact than a
+/// `BTreeMap` when the keys are close together. Values are encoded as usual, each following its
+/// key.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeltaKeyMap<V>(pub BTreeMap<u64, V>);
+
+impl<V: Binary> DeltaKeyMap<V> {
+    fn parse_with(bs: &[u8], parse: Parser<V>) -> Option<(Self, &[u8])> {
+        let (n, mut bs) = parse_len(bs)?;
+        let mut m = BTreeMap::new();
+        let mut previous = None;
+        for _i in 0..n {
+            let (key, bs_prime) = match previous {
+                None => u64::parse(bs)?,
+                Some(previous) => {
+                    let (delta, bs_prime) = parse_varint(bs)?;
+                    if delta == 0 {
+                        return None;
+                    }
+                    (u64::checked_add(previous, delta)?, bs_prime)
+                }
+            };
+            let (v, bs_prime) = parse(bs_prime)?;
+            m.insert(key, v);
+            previous = Some(key);
+            bs = bs_prime;
+        }
+        Some((DeltaKeyMap(m), bs))
+    }
+}
+
+impl<V: Binary> Binary for DeltaKeyMap<V> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, V::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_with(bs, V::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        let mut previous = None;
+        for (k, v) in self.0.iter() {
+            match previous {
+                None => k.unparse(bs),
+                Some(previous) => unparse_varint(k - previous, bs),
+            }
+            v.unparse(bs);
+            previous = Some(*k);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        let mut previous = None;
+        for (k, v) in self.0.iter() {
+            match previous {
+                None => k.unparse(bs),
+                Some(previous) => unparse_varint(k - previous, bs),
+            }
+            v.unparse_canonical(bs);
+            previous = Some(*k);
+        }
+    }
+}
+
+/// A vector of `u64`s, encoded as the first followed by the difference between each one and the
+/// one before it, zigzag encoded so that small decreases are as short as small increases, as a
+/// variable length integer. This is much more compact than a `Vec` when neighboring values are
+/// close together, as in a sorted vector of timestamps.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DeltaVec(pub Vec<u64>);
+
+// Maps integers of small magnitude to small unsigned integers: 0, -1, 1, -2, 2 and so on to 0,
+// 1, 2, 3, 4.
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+impl Binary for DeltaVec {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, mut bs) = parse_len(bs)?;
+        let mut v = Vec::<u64>::new();
+        for _i in 0..n {
+            let (x, bs_prime) = match v.last() {
+                None => u64::parse(bs)?,
+                Some(previous) => {
+                    let (delta, bs_prime) = parse_varint(bs)?;
+                    (previous.wrapping_add_signed(unzigzag(delta)), bs_prime)
+                }
+            };
+            v.push(x);
+            bs = bs_prime;
+        }
+        Some((DeltaVec(v), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        let Some(first) = self.0.first() else {
+            return;
+        };
+        first.unparse(bs);
+        for pair in self.0.windows(2) {
+            unparse_varint(zigzag(pair[1].wrapping_sub(pair[0]) as i64), bs);
+        }
+    }
+}
+
+/// A vector of unsigned integers in frame of reference encoding: the least of them, followed by
+/// the difference between each one and the least in the fewest bytes which fit the largest
+/// difference. This is much more compact than a `Vec` when the values are clustered together.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ForVec<T>(pub Vec<T>);
+
+// The number of bytes needed to hold the given offset.
+fn offset_width(offset: u64) -> usize {
+    (64 - offset.leading_zeros() as usize).div_ceil(8)
+}
+
+impl<T: Copy + Into<u64> + TryFrom<u64>> Binary for ForVec<T> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_len(bs)?;
+        if n == 0 {
+            return Some((ForVec(Vec::new()), bs));
+        }
+        let (base, bs) = u64::parse(bs)?;
+        let (width, bs) = u8::parse(bs)?;
+        let width = width as usize;
+        if width > 8 || bs.len() < n.checked_mul(width)? {
+            return None;
+        }
+        // values equal to the base take no bytes, so like any elements encoded as nothing there
+        // may only be so many of them
+        if width == 0 {
+            ensure_progress(bs, bs, n)?;
+        }
+        let (packed, bs) = bs.split_at(n * width);
+        // the checks above bound the length by the input, or by the number of empty elements
+        let mut v = Vec::with_capacity(n);
+        let mut max_offset = 0;
+        let mut min_offset = u64::MAX;
+        for i in 0..n {
+            let mut offset_bytes = [0; 8];
+            offset_bytes[..width].copy_from_slice(&packed[i * width..(i + 1) * width]);
+            let offset = u64::from_le_bytes(offset_bytes);
+            max_offset = max_offset.max(offset);
+            min_offset = min_offset.min(offset);
+            v.push(T::try_from(base.checked_add(offset)?).ok()?);
+        }
+        // the base must be the least value and the width the least that fits, so that each
+        // vector has one encoding
+        if min_offset != 0 || offset_width(max_offset) != width {
+            return None;
+        }
+        Some((ForVec(v), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        let Some(base) = self.0.iter().map(|&a| a.into()).min() else {
+            return;
+        };
+        let max = self.0.iter().map(|&a| a.into()).max().unwrap_or(base);
+        let width = offset_width(max - base);
+        base.unparse(bs);
+        (width as u8).unparse(bs);
+        for &a in &self.0 {
+            bs.write_bytes(&(a.into() - base).to_le_bytes()[..width]);
+        }
+    }
+}
+
+/// A vector of booleans packed eight to a byte: the number of bits as a length prefix, followed by
+/// the bits, the lowest bit of each byte first. The unused bits of the last byte are written as
+/// zero and ignored when parsing, except by `parse_strict`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PackedBits(pub Vec<bool>);
+
+impl PackedBits {
+    fn parse_padded(bs: &[u8], strict: bool) -> Option<(Self, &[u8])> {
+        let (len, bs) = parse_len(bs)?;
+        let n = len.div_ceil(8);
+        if bs.len() < n {
+            return None;
+        }
+        let (packed, bs) = bs.split_at(n);
+        let bits: Vec<bool> = (0..len)
+            .map(|index| packed[index / 8] & (1 << (index % 8)) != 0)
+            .collect();
+        if strict && len % 8 != 0 && packed[n - 1] >> (len % 8) != 0 {
+            return None;
+        }
+        Some((PackedBits(bits), bs))
+    }
+}
+
+impl Binary for PackedBits {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_padded(bs, false)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_padded(bs, true)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.0.len(), bs);
+        for chunk in self.0.chunks(8) {
+            let byte = chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (bit, &set)| byte | (set as u8) << bit);
+            bs.push(byte);
+        }
+    }
+}
+
+/// A value followed by the CRC-32 of its encoding as a `u32`, so that corruption of the stored
+/// bytes is detected when parsing rather than yielding a wrong value.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Checksummed<T>(pub T);
+
+// The table of remainders for each byte of the CRC-32 used by zlib, PNG, and Ethernet.
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc32(bs: &[u8]) -> u32 {
+    !bs.iter().fold(!0, |crc, &b| {
+        (crc >> 8) ^ CRC32_TABLE[((crc ^ b as u32) & 0xff) as usize]
+    })
+}
+
+impl<T: Binary> Checksummed<T> {
+    fn parse_checked(bs: &[u8], parse: Parser<T>) -> Option<(Self, &[u8])> {
+        let (t, rest) = parse(bs)?;
+        let (checksum, rest) = u32::parse(rest)?;
+        if checksum != crc32(&bs[..bs.len() - rest.len() - 4]) {
+            return None;
+        }
+        Some((Checksummed(t), rest))
+    }
+}
+
+impl<T: Binary> Binary for Checksummed<T> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_checked(bs, T::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        Self::parse_checked(bs, T::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let payload = self.0.to_bytes();
+        bs.write_bytes(&payload);
+        crc32(&payload).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let payload = self.0.to_canonical_bytes();
+        bs.write_bytes(&payload);
+        crc32(&payload).unparse(bs);
+    }
+}
+
+impl Binary for i128 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (i128_bytes, bs) = parse_bytes::<16>(bs)?;
+        Some((i128::from_le_bytes(*i128_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for u128 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (u128_bytes, bs) = parse_bytes::<16>(bs)?;
+        Some((u128::from_le_bytes(*u128_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for u64 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (u64_bytes, bs) = parse_bytes::<8>(bs)?;
+        Some((u64::from_le_bytes(*u64_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for i64 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (i64_bytes, bs) = parse_bytes::<8>(bs)?;
+        Some((i64::from_le_bytes(*i64_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for u32 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (u32_bytes, bs) = parse_bytes::<4>(bs)?;
+        Some((u32::from_le_bytes(*u32_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for i32 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (i32_bytes, bs) = parse_bytes::<4>(bs)?;
+        Some((i32::from_le_bytes(*i32_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes())
+    }
+}
+
+impl Binary for u16 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (u16_bytes, bs) = parse_bytes::<2>(bs)?;
+        Some((u16::from_le_bytes(*u16_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+}
+
+impl Binary for i16 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (i16_bytes, bs) = parse_bytes::<2>(bs)?;
+        Some((i16::from_le_bytes(*i16_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+}
+
+impl Binary for u8 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (u8_byte, bs) = parse_bytes::<1>(bs)?;
+        Some((u8::from_le_bytes(*u8_byte), bs))
+    }
+
+    fn parse_vec(bs: &[u8]) -> Option<(Vec<Self>, &[u8])> {
+        let (n, bs) = parse_len(bs)?;
+        let (bytes, bs) = bs.split_at_checked(n)?;
+        Some((bytes.to_vec(), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.push(*self);
+    }
+}
+impl Binary for i8 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (i8_byte, bs) = parse_bytes::<1>(bs)?;
+        Some((i8::from_le_bytes(*i8_byte), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+}
+
+impl Binary for bool {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (b, bs) = u8::parse(bs)?;
+        if b == 1 {
+            Some((true, bs))
+        } else if b == 0 {
+            Some((false, bs))
+        } else {
+            None
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.push(if *self { 1 } else { 0 });
+    }
+}
+
+impl Binary for char {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = u32::parse(bs)?;
+        Some((char::from_u32(n)?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        u32::from(*self).unparse(bs);
+    }
+}
+
+/// A character encoded as its one to four byte UTF-8 sequence, rather than as the four byte
+/// `u32` used for `char`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactChar(pub char);
+
+impl Binary for CompactChar {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let width = match bs.first()? {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => return None,
+        };
+        let (c, bs) = bs.split_at_checked(width)?;
+        let c = std::str::from_utf8(c).ok()?.chars().next()?;
+        Some((CompactChar(c), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(self.0.encode_utf8(&mut [0; 4]).as_bytes());
+    }
+}
+
+impl Binary for String {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (ss, bs) = <Vec<u8> as Binary>::parse(bs)?;
+        match String::from_utf8(ss) {
+            Err(_e) => None,
+            Ok(s) => Some((s, bs)),
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        bs.write_bytes(self.as_bytes());
+    }
+}
+
+#[cfg(unix)]
+impl Binary for OsString {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        use std::os::unix::ffi::OsStringExt;
+        let (os_bytes, bs) = <Vec<u8> as Binary>::parse(bs)?;
+        Some((OsString::from_vec(os_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use std::os::unix::ffi::OsStrExt;
+        unparse_len(self.len(), bs);
+        bs.write_bytes(self.as_bytes());
+    }
+}
+
+// Windows OS strings are sequences of possibly ill-formed UTF-16, so we encode them as a vector of
+// `u16`s and the encoding is not portable between Windows and Unix.
+#[cfg(windows)]
+impl Binary for OsString {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        use std::os::windows::ffi::OsStringExt;
+        let (wide, bs) = <Vec<u16> as Binary>::parse(bs)?;
+        Some((OsString::from_wide(&wide), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use std::os::windows::ffi::OsStrExt;
+        self.encode_wide().collect::<Vec<u16>>().unparse(bs);
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl Binary for PathBuf {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (os_string, bs) = OsString::parse(bs)?;
+        Some((PathBuf::from(os_string), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_os_str().to_os_string().unparse(bs);
+    }
+}
+
+impl Binary for f32 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (f32_bytes, bs) = parse_bytes::<4>(bs)?;
+        Some((f32::from_le_bytes(*f32_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+
+    // every NaN is written as the same one, while both zeros are written as they are
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let canonical = if self.is_nan() { f32::NAN } else { *self };
+        bs.write_bytes(&canonical.to_le_bytes());
+    }
+}
+
+impl Binary for f64 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (f64_bytes, bs) = parse_bytes::<8>(bs)?;
+        Some((f64::from_le_bytes(*f64_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+
+    // every NaN is written as the same one, while both zeros are written as they are
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let canonical = if self.is_nan() { f64::NAN } else { *self };
+        bs.write_bytes(&canonical.to_le_bytes());
+    }
+}
+
+// How many of the `n` entries of a hash map or set to reserve room for once the first, parsed
+// from `before`, has left `after`: as many as the input could hold were they all as long as the
+// first, so that a hostile length can't make us reserve more room than the input could fill.
+fn entries_to_reserve(n: usize, before: &[u8], after: &[u8]) -> usize {
+    let first = before.len() - after.len();
+    n.min(1 + after.len() / first.max(1))
+}
+
+// Hash maps and sets may use any hasher which can be built by default.
+impl<Key, Value, H> Binary for HashMap<Key, Value, H>
+where
+    Key: Binary + std::hash::Hash + Eq,
+    Value: Binary,
+    H: std::hash::BuildHasher + Default,
+{
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = HashMap::with_hasher(H::default());
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                let (v, bs_prime) = Value::parse(bs_prime)?;
+                if i == 0 {
+                    m.reserve(entries_to_reserve(n, bs, bs_prime));
+                }
+                m.insert(k, v);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (entries, bs) = parse_elements(bs, <(Key, Value)>::parse_strict)?;
+            let n = entries.len();
+            let m: HashMap<Key, Value, H> = entries.into_iter().collect();
+            (m.len() == n).then_some((m, bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for (k, v) in self {
+            k.unparse(bs);
+            v.unparse(bs);
+        }
+    }
+
+    // entries are ordered by the canonical encoding of their keys
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let mut entries: Vec<(Vec<u8>, &Value)> = self
+            .iter()
+            .map(|(k, v)| (k.to_canonical_bytes(), v))
+            .collect();
+        entries.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        unparse_len(entries.len(), bs);
+        for (k, v) in entries {
+            bs.write_bytes(&k);
+            v.unparse_canonical(bs);
+        }
+    }
+}
+
+// Entries are written in ascending order of their keys. `parse` accepts them in any order, the last
+// of any repeated key winning, but `parse_strict` requires each key to be greater than the last.
+impl<Key: Binary + Ord, Value: Binary> Binary for BTreeMap<Key, Value> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = BTreeMap::new();
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                let (v, bs_prime) = Value::parse(bs_prime)?;
+                m.insert(k, v);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (entries, bs) = parse_elements(bs, <(Key, Value)>::parse_strict)?;
+            if !entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+                return None;
+            }
+            Some((entries.into_iter().collect(), bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for (k, v) in self {
+            k.unparse(bs);
+            v.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for (k, v) in self {
+            k.unparse_canonical(bs);
+            v.unparse_canonical(bs);
+        }
+    }
+}
+
+impl<Key: Binary + std::hash::Hash + Eq, H: std::hash::BuildHasher + Default> Binary
+    for HashSet<Key, H>
+{
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = HashSet::with_hasher(H::default());
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                if i == 0 {
+                    m.reserve(entries_to_reserve(n, bs, bs_prime));
+                }
+                m.insert(k);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (elements, bs) = parse_elements(bs, Key::parse_strict)?;
+            let n = elements.len();
+            let m: HashSet<Key, H> = elements.into_iter().collect();
+            (m.len() == n).then_some((m, bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in self {
+            k.unparse(bs);
+        }
+    }
+
+    // elements are ordered by their canonical encodings
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let mut elements: Vec<Vec<u8>> = self.iter().map(Binary::to_canonical_bytes).collect();
+        elements.sort();
+        unparse_len(elements.len(), bs);
+        for k in elements {
+            bs.write_bytes(&k);
+        }
+    }
+}
+
+impl<Key: Binary + Ord> Binary for BTreeSet<Key> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = BTreeSet::new();
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                m.insert(k);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (elements, bs) = parse_elements(bs, Key::parse_strict)?;
+            if !elements.windows(2).all(|pair| pair[0] < pair[1]) {
+                return None;
+            }
+            Some((elements.into_iter().collect(), bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in self {
+            k.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+impl<Key: Binary + Ord> Binary for BinaryHeap<Key> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = BinaryHeap::new();
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                m.push(k);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (elements, bs) = parse_elements(bs, Key::parse_strict)?;
+            if !elements.windows(2).all(|pair| pair[0] >= pair[1]) {
+                return None;
+            }
+            Some((elements.into(), bs))
+        })
+    }
+
+    // elements are ordered from greatest to least, so equal heaps always have the same encoding
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in sorted_descending(self) {
+            k.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in sorted_descending(self) {
+            k.unparse_canonical(bs);
+        }
+    }
+}
+
+fn sorted_descending<Key: Ord>(heap: &BinaryHeap<Key>) -> Vec<&Key> {
+    let mut elements: Vec<&Key> = heap.iter().collect();
+    elements.sort_by(|a, b| b.cmp(a));
+    elements
+}
+
+impl<Key: Binary> Binary for VecDeque<Key> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = VecDeque::new();
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                m.push_back(k);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (elements, bs) = parse_elements(bs, Key::parse_strict)?;
+            Some((elements.into_iter().collect(), bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in self {
+            k.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+impl<Key: Binary> Binary for LinkedList<Key> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (n, mut bs) = parse_len(bs)?;
+            let mut m = LinkedList::new();
+            for i in 0..n {
+                let (k, bs_prime) = Key::parse(bs)?;
+                m.push_back(k);
+                ensure_progress(bs, bs_prime, n - i)?;
+                bs = bs_prime;
+            }
+            Some((m, bs))
+        })
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| {
+            let (elements, bs) = parse_elements(bs, Key::parse_strict)?;
+            Some((elements.into_iter().collect(), bs))
+        })
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for k in self {
+            k.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for a in self {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+impl<A: Binary + Copy> Binary for Cell<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse(bs)?;
+        Some((Cell::new(a), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        Some((Cell::new(a), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.get().unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.get().unparse_canonical(bs);
+    }
+}
+
+// A RefCell is encoded as its contents, so encoding one which is mutably borrowed panics, as
+// there are no contents it can read.
+impl<A: Binary> Binary for RefCell<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse(bs)?;
+        Some((RefCell::new(a), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        Some((RefCell::new(a), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.try_borrow()
+            .expect("cannot encode a RefCell while it is mutably borrowed")
+            .unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.try_borrow()
+            .expect("cannot encode a RefCell while it is mutably borrowed")
+            .unparse_canonical(bs);
+    }
+}
+
+// Locks are encoded as their contents. A poisoned lock is still read, since the panic which
+// poisoned it does not stop its contents from being encoded.
+impl<A: Binary> Binary for Mutex<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse(bs)?;
+        Some((Mutex::new(a), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        Some((Mutex::new(a), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.lock().unwrap_or_else(|e| e.into_inner()).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .unparse_canonical(bs);
+    }
+}
+
+impl<A: Binary> Binary for RwLock<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse(bs)?;
+        Some((RwLock::new(a), bs))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (a, bs) = A::parse_strict(bs)?;
+        Some((RwLock::new(a), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.read().unwrap_or_else(|e| e.into_inner()).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.read()
+            .unwrap_or_else(|e| e.into_inner())
+            .unparse_canonical(bs);
+    }
+}
+
+// Once cells are encoded as a byte which is 0 if the cell is empty and 1 if it is full, followed
+// by the value if there is one.
+impl<A: Binary> Binary for OnceCell<A> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (full, bs) = bool::parse(bs)?;
+        if full {
+            let (a, bs) = A::parse(bs)?;
+            Some((OnceCell::from(a), bs))
+        } else {
+            Some((OnceCell::new(), bs))
+        }
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (full, bs) = bool::parse(bs)?;
+        if full {
+            let (a, bs) = A::parse_strict(bs)?;
+            Some((OnceCell::from(a), bs))
+        } else {
+            Some((OnceCell::new(), bs))
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self.get() {
+            Some(a) => {
+                true.unparse(bs);
+                a.unparse(bs);
+            }
+            None => false.unparse(bs),
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self.get() {
+            Some(a) => {
+                true.unparse(bs);
+                a.unparse_canonical(bs);
+            }
+            None => false.unparse(bs),
+        }
+    }
+}
+
+// Lazy values are forced and encoded exactly as their value. As the function initializing them
+// can't be encoded, they can only be written; parse the value's type instead.
+impl<A: Binary, F: FnOnce() -> A> Encode for LazyLock<A, F> {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LazyLock::force(self).unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        LazyLock::force(self).unparse_canonical(bs);
+    }
+}
+
+// Atomics are encoded as a snapshot of their value, loaded with sequential consistency.
+macro_rules! impl_binary_atomic {
+    ($($atomic:ty => $inner:ty),* $(,)?) => {
+        $(
+            impl Binary for $atomic {
+                fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    let (x, bs) = <$inner>::parse(bs)?;
+                    Some((<$atomic>::new(x), bs))
+                }
+
+                fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                    self.load(Ordering::SeqCst).unparse(bs);
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_atomic! {
+    AtomicBool => bool,
+    AtomicU8 => u8,
+    AtomicU16 => u16,
+    AtomicU32 => u32,
+    AtomicU64 => u64,
+    AtomicI8 => i8,
+    AtomicI16 => i16,
+    AtomicI32 => i32,
+    AtomicI64 => i64,
+}
+
+/// A duration of less than one second, encoded as a `u32` count of nanoseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubsecDuration(u32);
+
+impl SubsecDuration {
+    /// The number of nanoseconds in this duration, which is always less than one billion.
+    pub fn as_nanos(&self) -> u32 {
+        self.0
+    }
+}
+
+/// The error converting a [Duration] of one second or more into a [SubsecDuration].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubsecDurationError;
+
+impl std::fmt::Display for SubsecDurationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("duration is not less than one second")
+    }
+}
+
+impl std::error::Error for SubsecDurationError {}
+
+impl TryFrom<Duration> for SubsecDuration {
+    type Error = SubsecDurationError;
+
+    /// Fails if the duration has any whole seconds.
+    fn try_from(d: Duration) -> Result<Self, SubsecDurationError> {
+        if d.as_secs() == 0 {
+            Ok(SubsecDuration(d.subsec_nanos()))
+        } else {
+            Err(SubsecDurationError)
+        }
+    }
+}
+
+impl From<SubsecDuration> for Duration {
+    fn from(d: SubsecDuration) -> Duration {
+        Duration::from_nanos(d.0 as u64)
+    }
+}
+
+impl Binary for SubsecDuration {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (nanos, bs) = u32::parse(bs)?;
+        if nanos < 1_000_000_000 {
+            Some((SubsecDuration(nanos), bs))
+        } else {
+            None
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+}
+
+/// A percentage from 0 to 100, encoded as a `u8`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Percent(u8);
+
+impl Percent {
+    /// Clamps the given value to at most 100.
+    pub fn new(percent: u8) -> Self {
+        Percent(percent.min(100))
+    }
+
+    /// The percentage, which is never more than 100.
+    pub fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Binary for Percent {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (percent, bs) = u8::parse(bs)?;
+        if percent <= 100 {
+            Some((Percent(percent), bs))
+        } else {
+            None
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+}
+
+/// An angle from 0 up to but not including 360 degrees in tenths of a degree, encoded as a `u16`
+/// count of tenths. As 360 degrees is the same angle as 0, it has no encoding of its own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Degrees(u16);
+
+impl Degrees {
+    /// Clamps the given number of tenths of a degree to at most 3599.
+    pub fn from_tenths(tenths: u16) -> Self {
+        Degrees(tenths.min(3599))
+    }
+
+    /// The number of tenths of a degree, which is never more than 3599.
+    pub fn as_tenths(&self) -> u16 {
+        self.0
+    }
+
+    /// The angle in degrees.
+    pub fn as_degrees(&self) -> f64 {
+        self.0 as f64 / 10.0
+    }
+}
+
+impl Binary for Degrees {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (tenths, bs) = u16::parse(bs)?;
+        if tenths < 3600 {
+            Some((Degrees(tenths), bs))
+        } else {
+            None
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.0.unparse(bs);
+    }
+}
+
+// Times are encoded as the `i64` seconds and `u32` nanoseconds since the Unix epoch, where the
+// seconds are negative for times before the epoch and the nanoseconds always count forward, so
+// half a second before the epoch is -1 seconds and 500,000,000 nanoseconds.
+impl Binary for SystemTime {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (secs, bs) = i64::parse(bs)?;
+        let (nanos, bs) = u32::parse(bs)?;
+        if nanos >= 1_000_000_000 {
+            return None;
+        }
+        let whole = if secs >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))?
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+        };
+        Some((whole.checked_add(Duration::from_nanos(nanos as u64))?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let (secs, nanos) = split_offset(self.duration_since(UNIX_EPOCH).map_err(|e| e.duration()));
+        secs.unparse(bs);
+        nanos.unparse(bs);
+    }
+}
+
+// Splits the time after, or before, an epoch into signed seconds and forward counting
+// nanoseconds, as times are encoded.
+fn split_offset(offset: Result<Duration, Duration>) -> (i64, u32) {
+    match offset {
+        Ok(after) => (after.as_secs() as i64, after.subsec_nanos()),
+        Err(before) => {
+            if before.subsec_nanos() == 0 {
+                (-(before.as_secs() as i64), 0)
+            } else {
+                (
+                    -(before.as_secs() as i64) - 1,
+                    1_000_000_000 - before.subsec_nanos(),
+                )
+            }
+        }
+    }
+}
+
+/// Writes an instant as its offset from the given epoch, encoded just as a `SystemTime` is
+/// encoded as its offset from the Unix epoch. Instants mean nothing outside of the process which
+/// took them, so they only round trip through [parse_instant] with the same epoch.
+pub fn unparse_instant<S: ByteSink + ?Sized>(i: Instant, epoch: Instant, bs: &mut S) {
+    let offset = i
+        .checked_duration_since(epoch)
+        .ok_or_else(|| epoch.duration_since(i));
+    let (secs, nanos) = split_offset(offset);
+    secs.unparse(bs);
+    nanos.unparse(bs);
+}
+
+/// Reads an instant written by [unparse_instant] with the same epoch.
+pub fn parse_instant(bs: &[u8], epoch: Instant) -> Option<(Instant, &[u8])> {
+    let (secs, bs) = i64::parse(bs)?;
+    let (nanos, bs) = u32::parse(bs)?;
+    if nanos >= 1_000_000_000 {
+        return None;
+    }
+    let whole = if secs >= 0 {
+        epoch.checked_add(Duration::from_secs(secs as u64))?
+    } else {
+        epoch.checked_sub(Duration::from_secs(secs.unsigned_abs()))?
+    };
+    Some((whole.checked_add(Duration::from_nanos(nanos as u64))?, bs))
+}
+
+/// A serializable description of a command to run, which can be turned into a
+/// [std::process::Command].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl Binary for CommandSpec {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (program, bs) = String::parse(bs)?;
+        let (args, bs) = Vec::parse(bs)?;
+        let (env, bs) = BTreeMap::parse(bs)?;
+        Some((CommandSpec { program, args, env }, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.program.unparse(bs);
+        self.args.unparse(bs);
+        self.env.unparse(bs);
+    }
+}
+
+impl From<&CommandSpec> for std::process::Command {
+    /// The environment variables are set in addition to those inherited from this process.
+    fn from(spec: &CommandSpec) -> std::process::Command {
+        let mut command = std::process::Command::new(&spec.program);
+        command.args(&spec.args).envs(&spec.env);
+        command
+    }
+}
+
+impl From<CommandSpec> for std::process::Command {
+    fn from(spec: CommandSpec) -> std::process::Command {
+        std::process::Command::from(&spec)
+    }
+}
+
+/// Describes the type of a value to be decoded by [parse_schema].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TypeCode {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Bool,
+    Char,
+    String,
+    Bytes,
+}
+
+/// A value decoded according to a [TypeCode].
+#[derive(Clone, Debug, PartialEq)]
+pub enum DynValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    U128(u128),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+impl DynValue {
+    /// Deserialize a value of the type described by the code, potentially leaving more input.
+    pub fn parse(code: TypeCode, bs: &[u8]) -> Option<(Self, &[u8])> {
+        fn wrap<A: Binary>(bs: &[u8], f: fn(A) -> DynValue) -> Option<(DynValue, &[u8])> {
+            let (a, bs) = A::parse(bs)?;
+            Some((f(a), bs))
+        }
+        match code {
+            TypeCode::U8 => wrap(bs, DynValue::U8),
+            TypeCode::U16 => wrap(bs, DynValue::U16),
+            TypeCode::U32 => wrap(bs, DynValue::U32),
+            TypeCode::U64 => wrap(bs, DynValue::U64),
+            TypeCode::U128 => wrap(bs, DynValue::U128),
+            TypeCode::I8 => wrap(bs, DynValue::I8),
+            TypeCode::I16 => wrap(bs, DynValue::I16),
+            TypeCode::I32 => wrap(bs, DynValue::I32),
+            TypeCode::I64 => wrap(bs, DynValue::I64),
+            TypeCode::I128 => wrap(bs, DynValue::I128),
+            TypeCode::F32 => wrap(bs, DynValue::F32),
+            TypeCode::F64 => wrap(bs, DynValue::F64),
+            TypeCode::Bool => wrap(bs, DynValue::Bool),
+            TypeCode::Char => wrap(bs, DynValue::Char),
+            TypeCode::String => wrap(bs, DynValue::String),
+            TypeCode::Bytes => wrap(bs, DynValue::Bytes),
+        }
+    }
+
+    /// The code describing the type of this value.
+    pub fn type_code(&self) -> TypeCode {
+        match self {
+            DynValue::U8(_) => TypeCode::U8,
+            DynValue::U16(_) => TypeCode::U16,
+            DynValue::U32(_) => TypeCode::U32,
+            DynValue::U64(_) => TypeCode::U64,
+            DynValue::U128(_) => TypeCode::U128,
+            DynValue::I8(_) => TypeCode::I8,
+            DynValue::I16(_) => TypeCode::I16,
+            DynValue::I32(_) => TypeCode::I32,
+            DynValue::I64(_) => TypeCode::I64,
+            DynValue::I128(_) => TypeCode::I128,
+            DynValue::F32(_) => TypeCode::F32,
+            DynValue::F64(_) => TypeCode::F64,
+            DynValue::Bool(_) => TypeCode::Bool,
+            DynValue::Char(_) => TypeCode::Char,
+            DynValue::String(_) => TypeCode::String,
+            DynValue::Bytes(_) => TypeCode::Bytes,
+        }
+    }
+
+    /// Serialize this value to the vector, exactly as its underlying type would.
+    pub fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        match self {
+            DynValue::U8(x) => x.unparse(bs),
+            DynValue::U16(x) => x.unparse(bs),
+            DynValue::U32(x) => x.unparse(bs),
+            DynValue::U64(x) => x.unparse(bs),
+            DynValue::U128(x) => x.unparse(bs),
+            DynValue::I8(x) => x.unparse(bs),
+            DynValue::I16(x) => x.unparse(bs),
+            DynValue::I32(x) => x.unparse(bs),
+            DynValue::I64(x) => x.unparse(bs),
+            DynValue::I128(x) => x.unparse(bs),
+            DynValue::F32(x) => x.unparse(bs),
+            DynValue::F64(x) => x.unparse(bs),
+            DynValue::Bool(x) => x.unparse(bs),
+            DynValue::Char(x) => x.unparse(bs),
+            DynValue::String(x) => x.unparse(bs),
+            DynValue::Bytes(x) => x.unparse(bs),
+        }
+    }
+}
+
+/// Parse one value after another, each according to the corresponding type code, potentially
+/// leaving more input. This lets us decode records whose layout is only known at runtime.
+pub fn parse_schema<'a>(codes: &[TypeCode], mut bs: &'a [u8]) -> Option<(Vec<DynValue>, &'a [u8])> {
+    let mut values = Vec::with_capacity(codes.len());
+    for code in codes {
+        let (value, bs_prime) = DynValue::parse(*code, bs)?;
+        values.push(value);
+        bs = bs_prime;
+    }
+    Some((values, bs))
+}
+
+#[cfg(feature = "bls12_381")]
+use bls12_381::{G1Affine, G1Projective, G2Affine, G2Projective, Scalar};
+
+#[cfg(feature = "bls12_381")]
+impl Binary for Scalar {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_bytes());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (scalar_bytes, bs) = parse_bytes::<32>(bs)?;
+        let scalar = Option::from(Scalar::from_bytes(scalar_bytes))?;
+        Some((scalar, bs))
+    }
+}
+
+#[cfg(feature = "bls12_381")]
+impl Binary for G1Affine {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_compressed());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (g1affine_bytes, bs) = parse_bytes::<48>(bs)?;
+        let g1affine = Option::from(G1Affine::from_compressed(g1affine_bytes))?;
+        Some((g1affine, bs))
+    }
+}
+
+#[cfg(feature = "bls12_381")]
+impl Binary for G1Projective {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&G1Affine::from(self).to_compressed());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (g1affine_bytes, bs) = parse_bytes::<48>(bs)?;
+        let g1projective = Option::from(G1Affine::from_compressed(g1affine_bytes))
+            .map(|x: G1Affine| G1Projective::from(x))?;
+        Some((g1projective, bs))
+    }
+}
+
+#[cfg(feature = "bls12_381")]
+impl Binary for G2Affine {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_compressed());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (g2affine_bytes, bs) = parse_bytes::<96>(bs)?;
+        let g2affine = Option::from(G2Affine::from_compressed(g2affine_bytes))?;
+        Some((g2affine, bs))
+    }
+}
+
+#[cfg(feature = "bls12_381")]
+impl Binary for G2Projective {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&G2Affine::from(self).to_compressed());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (g2affine_bytes, bs) = parse_bytes::<96>(bs)?;
+        let g2projective = Option::from(G2Affine::from_compressed(g2affine_bytes))
+            .map(|x: G2Affine| G2Projective::from(x))?;
+        Some((g2projective, bs))
+    }
+}
+
+#[cfg(feature = "curve25519-dalek")]
+use curve25519_dalek::{
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar as RistrettoScalar,
+};
+
+#[cfg(feature = "curve25519-dalek")]
+impl Binary for CompressedRistretto {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (compressed_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
+        Some((CompressedRistretto::from_slice(&compressed_bytes).ok()?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_bytes().unparse(bs);
+    }
+}
+
+#[cfg(feature = "curve25519-dalek")]
+impl Binary for RistrettoPoint {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (cr, bs) = CompressedRistretto::parse(bs)?;
+        Some((CompressedRistretto::decompress(&cr)?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.compress().unparse(bs);
+    }
+}
+
+#[cfg(feature = "curve25519-dalek")]
+impl Binary for RistrettoScalar {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (scalar_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
+        Some((
+            Option::from(RistrettoScalar::from_canonical_bytes(scalar_bytes))?,
+            bs,
+        ))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_bytes().unparse(bs);
+    }
+}
+
+#[cfg(feature = "ed25519-dalek")]
+impl Binary for ed25519_dalek::VerifyingKey {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (key_bytes, bs) = parse_bytes::<32>(bs)?;
+        Some((ed25519_dalek::VerifyingKey::from_bytes(key_bytes).ok()?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_bytes().unparse(bs);
+    }
+}
+
+#[cfg(feature = "ed25519-dalek")]
+impl Binary for ed25519_dalek::Signature {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (signature_bytes, bs) = parse_bytes::<64>(bs)?;
+        Some((ed25519_dalek::Signature::from_bytes(signature_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.to_bytes().unparse(bs);
+    }
+}
+
+// Signing keys are encoded as their 32 byte seed.
+#[cfg(feature = "ed25519-dalek")]
+impl Binary for ed25519_dalek::SigningKey {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (seed, bs) = parse_bytes::<32>(bs)?;
+        Some((ed25519_dalek::SigningKey::from_bytes(seed), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_bytes().unparse(bs);
+    }
+}
+
+// Public keys are encoded as compressed SEC1 points.
+#[cfg(feature = "k256")]
+impl Binary for k256::PublicKey {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use k256::elliptic_curve::sec1::ToEncodedPoint;
+        bs.write_bytes(self.to_encoded_point(true).as_bytes());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (public_key_bytes, bs) = parse_bytes::<33>(bs)?;
+        let public_key = k256::PublicKey::from_sec1_bytes(public_key_bytes).ok()?;
+        Some((public_key, bs))
+    }
+}
+
+// Signatures are encoded as r followed by s, and parsing rejects signatures with a high s, which
+// are malleable.
+#[cfg(feature = "k256")]
+impl Binary for k256::ecdsa::Signature {
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_bytes());
+    }
+
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (signature_bytes, bs) = parse_bytes::<64>(bs)?;
+        let signature = k256::ecdsa::Signature::from_slice(signature_bytes).ok()?;
+        if signature.normalize_s().is_some() {
+            return None;
+        }
+        Some((signature, bs))
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl Binary for blake3::Hash {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (hash_bytes, bs) = <[u8; 32] as Binary>::parse(bs)?;
+        Some((blake3::Hash::from_bytes(hash_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.as_bytes().unparse(bs);
+    }
+}
+
+#[cfg(feature = "blake3")]
+fn merkle_leaf<T: Binary>(item: &T) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[0]);
+    hasher.update(&item.to_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+#[cfg(feature = "blake3")]
+fn merkle_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[1]);
+    hasher.update(left);
+    hasher.update(right);
+    *hasher.finalize().as_bytes()
+}
+
+#[cfg(feature = "blake3")]
+fn merkle_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => merkle_node(left, right),
+            [last] => *last,
+            _ => unreachable!("chunks are of size one or two"),
+        })
+        .collect()
+}
+
+/// Computes the root of a binary Merkle tree whose leaves are the hashes of the encodings of the
+/// items. Leaves and interior nodes are hashed with distinct prefixes, and a node without a
+/// sibling is carried up to the next level unchanged. The root of no items is all zeros.
+#[cfg(feature = "blake3")]
+pub fn merkle_root<T: Binary>(items: &[T]) -> [u8; 32] {
+    let mut level: Vec<[u8; 32]> = items.iter().map(merkle_leaf).collect();
+    if level.is_empty() {
+        return [0; 32];
+    }
+    while level.len() > 1 {
+        level = merkle_level(&level);
+    }
+    level[0]
+}
+
+/// Computes the sibling hashes, from the leaves up, which prove the inclusion of the item at the
+/// given index in the tree built by [merkle_root]. Returns None if the index is out of bounds.
+#[cfg(feature = "blake3")]
+pub fn merkle_proof<T: Binary>(items: &[T], mut index: usize) -> Option<Vec<[u8; 32]>> {
+    if index >= items.len() {
+        return None;
+    }
+    let mut level: Vec<[u8; 32]> = items.iter().map(merkle_leaf).collect();
+    let mut proof = Vec::new();
+    while level.len() > 1 {
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(*sibling);
+        }
+        index /= 2;
+        level = merkle_level(&level);
+    }
+    Some(proof)
+}
+
+/// Checks a proof produced by [merkle_proof] that the item sits at the given index of a tree
+/// with the given number of leaves and the given root.
+#[cfg(feature = "blake3")]
+pub fn verify_merkle_proof<T: Binary>(
+    root: &[u8; 32],
+    item: &T,
+    mut index: usize,
+    mut len: usize,
+    proof: &[[u8; 32]],
+) -> bool {
+    if index >= len {
+        return false;
+    }
+    let mut hash = merkle_leaf(item);
+    let mut siblings = proof.iter();
+    while len > 1 {
+        if index % 2 == 1 {
+            match siblings.next() {
+                Some(sibling) => hash = merkle_node(sibling, &hash),
+                None => return false,
+            }
+        } else if index + 1 < len {
+            match siblings.next() {
+                Some(sibling) => hash = merkle_node(&hash, sibling),
+                None => return false,
+            }
+        }
+        index /= 2;
+        len = len.div_ceil(2);
+    }
+    siblings.next().is_none() && &hash == root
+}
+
+#[cfg(feature = "tinystr")]
+impl<const N: usize> Binary for tinystr::TinyAsciiStr<N> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (str_bytes, bs) = parse_bytes::<N>(bs)?;
+        Some((tinystr::TinyAsciiStr::try_from_raw(*str_bytes).ok()?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(self.all_bytes());
+    }
+}
+
+#[cfg(feature = "half")]
+impl Binary for half::f16 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (f16_bytes, bs) = parse_bytes::<2>(bs)?;
+        Some((half::f16::from_le_bytes(*f16_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+
+    // every NaN is written as the same one, as for `f32`
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let canonical = if self.is_nan() { half::f16::NAN } else { *self };
+        bs.write_bytes(&canonical.to_le_bytes());
+    }
+}
+
+#[cfg(feature = "half")]
+impl Binary for half::bf16 {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (bf16_bytes, bs) = parse_bytes::<2>(bs)?;
+        Some((half::bf16::from_le_bytes(*bf16_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.to_le_bytes());
+    }
+
+    // every NaN is written as the same one, as for `f32`
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let canonical = if self.is_nan() {
+            half::bf16::NAN
+        } else {
+            *self
+        };
+        bs.write_bytes(&canonical.to_le_bytes());
+    }
+}
+
+/// Serialize an array of plain old data by copying its memory wholesale. Unlike the `Binary`
+/// impls, this uses the native byte order of the machine and so is only portable between machines
+/// of the same endianness.
+#[cfg(feature = "bytemuck")]
+pub fn unparse_pod_array<T: bytemuck::Pod, const N: usize, S: ByteSink + ?Sized>(
+    arr: &[T; N],
+    bs: &mut S,
+) {
+    bs.write_bytes(bytemuck::cast_slice(arr.as_slice()));
+}
+
+/// Parse an array of plain old data written by [unparse_pod_array] by copying memory wholesale,
+/// potentially leaving more input.
+#[cfg(feature = "bytemuck")]
+pub fn parse_pod_array<T: bytemuck::Pod, const N: usize>(bs: &[u8]) -> Option<([T; N], &[u8])> {
+    let len = std::mem::size_of::<T>() * N;
+    if bs.len() < len {
+        return None;
+    }
+    let mut arr: [T; N] = std::array::from_fn(|_| T::zeroed());
+    bytemuck::cast_slice_mut::<T, u8>(arr.as_mut_slice()).copy_from_slice(&bs[..len]);
+    Some((arr, &bs[len..]))
+}
+
+// Dates are encoded as an `i32` count of days since the first day of the common era.
+#[cfg(feature = "chrono")]
+impl Binary for chrono::NaiveDate {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (days, bs) = i32::parse(bs)?;
+        Some((chrono::NaiveDate::from_num_days_from_ce_opt(days)?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use chrono::Datelike;
+        self.num_days_from_ce().unparse(bs);
+    }
+}
+
+// Times are encoded as a `u32` count of seconds since midnight followed by a `u32` count of
+// nanoseconds, which exceeds one billion only during a leap second.
+#[cfg(feature = "chrono")]
+impl Binary for chrono::NaiveTime {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (secs, bs) = u32::parse(bs)?;
+        let (nanos, bs) = u32::parse(bs)?;
+        Some((
+            chrono::NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos)?,
+            bs,
+        ))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use chrono::Timelike;
+        self.num_seconds_from_midnight().unparse(bs);
+        self.nanosecond().unparse(bs);
+    }
+}
+
+// Datetimes are encoded as an `i128` count of nanoseconds since the Unix epoch. The offset isn't
+// kept, so they are parsed in UTC.
+#[cfg(feature = "time")]
+impl Binary for time::OffsetDateTime {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (nanos, bs) = i128::parse(bs)?;
+        Some((
+            time::OffsetDateTime::from_unix_timestamp_nanos(nanos).ok()?,
+            bs,
+        ))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.unix_timestamp_nanos().unparse(bs);
+    }
+}
+
+// Dates are encoded as an `i32` Julian day number.
+#[cfg(feature = "time")]
+impl Binary for time::Date {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (day, bs) = i32::parse(bs)?;
+        Some((time::Date::from_julian_day(day).ok()?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.to_julian_day().unparse(bs);
+    }
+}
+
+// Durations, which may be negative, are encoded as an `i128` count of nanoseconds.
+#[cfg(feature = "time")]
+impl Binary for time::Duration {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (nanos, bs) = i128::parse(bs)?;
+        let secs = i64::try_from(nanos / 1_000_000_000).ok()?;
+        let subsec_nanos = (nanos % 1_000_000_000) as i32;
+        Some((time::Duration::new(secs, subsec_nanos), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.whole_nanoseconds().unparse(bs);
+    }
+}
+
+// Wide strings are encoded as a vector of `u16` code units. No check is made that the code units
+// are valid UTF-16, as `U16String` doesn't require that.
+#[cfg(feature = "widestring")]
+impl Binary for widestring::U16String {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (units, bs) = <Vec<u16> as Binary>::parse(bs)?;
+        Some((widestring::U16String::from_vec(units), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for unit in self.as_slice() {
+            unit.unparse(bs);
+        }
+    }
+}
+
+// Wide C strings are encoded like wide strings, without their nul terminator, and parsing fails
+// if there is a nul among the code units.
+#[cfg(feature = "widestring")]
+impl Binary for widestring::U16CString {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (units, bs) = <Vec<u16> as Binary>::parse(bs)?;
+        Some((widestring::U16CString::from_vec(units).ok()?, bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for unit in self.as_slice() {
+            unit.unparse(bs);
+        }
+    }
+}
+
+// The glam types are encoded as their f32 components, with matrices in column-major order and
+// quaternions as x, y, z, w.
+#[cfg(feature = "glam")]
+macro_rules! impl_binary_glam {
+    ($($glam:ty => $n:literal, $to:ident, $from:ident;)*) => {
+        $(
+            impl Binary for $glam {
+                fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+                    let (components, bs) = <[f32; $n]>::parse(bs)?;
+                    Some((<$glam>::$from(&components), bs))
+                }
+
+                fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                    self.$to().unparse(bs);
+                }
+
+                fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                    self.$to().unparse_canonical(bs);
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "glam")]
+impl_binary_glam! {
+    glam::Vec2 => 2, to_array, from_slice;
+    glam::Vec3 => 3, to_array, from_slice;
+    glam::Vec4 => 4, to_array, from_slice;
+    glam::Quat => 4, to_array, from_slice;
+    glam::Mat3 => 9, to_cols_array, from_cols_array;
+    glam::Mat4 => 16, to_cols_array, from_cols_array;
+}
+
+// SI quantities are encoded as their value in base units, since their dimension and units are
+// part of their type.
+#[cfg(feature = "uom")]
+impl<D, U, V> Binary for uom::si::Quantity<D, U, V>
+where
+    D: uom::si::Dimension + ?Sized,
+    U: uom::si::Units<V> + ?Sized,
+    V: Binary + uom::num_traits::Num + uom::Conversion<V>,
+{
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (value, bs) = V::parse(bs)?;
+        Some((
+            uom::si::Quantity {
+                dimension: std::marker::PhantomData,
+                units: std::marker::PhantomData,
+                value,
+            },
+            bs,
+        ))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.value.unparse(bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        self.value.unparse_canonical(bs);
+    }
+}
+
+// Decimals are encoded as rust_decimal serializes them, a `u32` of flags holding the sign and
+// scale followed by the 96 bit mantissa. Deserializing masks off the flags it doesn't use and
+// rescales scales over 28, so parsing rejects those instead, as they would not round trip.
+#[cfg(feature = "rust_decimal")]
+impl Binary for rust_decimal::Decimal {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (decimal_bytes, bs) = parse_bytes::<16>(bs)?;
+        let flags = u32::from_le_bytes([
+            decimal_bytes[0],
+            decimal_bytes[1],
+            decimal_bytes[2],
+            decimal_bytes[3],
+        ]);
+        let scale = (flags >> 16) & 0xff;
+        if flags & !0x80ff_0000 != 0 || scale > rust_decimal::Decimal::MAX_SCALE {
+            return None;
+        }
+        Some((rust_decimal::Decimal::deserialize(*decimal_bytes), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        bs.write_bytes(&self.serialize());
+    }
+}
+
+// Bitmaps are encoded in the portable roaring format, prefixed by its length in bytes, and parsing
+// fails unless the bitmap takes up exactly that many bytes.
+#[cfg(feature = "roaring")]
+impl Binary for roaring::RoaringBitmap {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_len(bs)?;
+        if bs.len() < n {
+            return None;
+        }
+        let (mut bitmap_bs, bs) = bs.split_at(n);
+        let bitmap = roaring::RoaringBitmap::deserialize_from(&mut bitmap_bs).ok()?;
+        if bitmap_bs.is_empty() {
+            Some((bitmap, bs))
+        } else {
+            None
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let mut bitmap_bs = Vec::with_capacity(self.serialized_size());
+        self.serialize_into(&mut bitmap_bs)
+            .expect("writing to a vector should never fail");
+        unparse_len(bitmap_bs.len(), bs);
+        bs.write_bytes(&bitmap_bs);
+    }
+}
+
+// Array vectors and strings are encoded exactly like a `Vec` and a `String`, but refuse to parse
+// more elements or bytes than their capacity.
+#[cfg(feature = "arrayvec")]
+fn parse_array_vec<T, const N: usize>(
+    bs: &[u8],
+    parse: Parser<T>,
+) -> Option<(arrayvec::ArrayVec<T, N>, &[u8])> {
+    let (n, mut bs) = parse_len(bs)?;
+    if n > N {
+        return None;
+    }
+    let mut v = arrayvec::ArrayVec::new();
+    for i in 0..n {
+        let (x, bs_prime) = parse(bs)?;
+        v.push(x);
+        ensure_progress(bs, bs_prime, n - i)?;
+        bs = bs_prime;
+    }
+    Some((v, bs))
+}
+
+#[cfg(feature = "arrayvec")]
+impl<T: Binary, const N: usize> Binary for arrayvec::ArrayVec<T, N> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_array_vec(bs, T::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_array_vec(bs, T::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        encode_many(self, bs);
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        for x in self {
+            x.unparse_canonical(bs);
+        }
+    }
+}
+
+#[cfg(feature = "arrayvec")]
+impl<const N: usize> Binary for arrayvec::ArrayString<N> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_len(bs)?;
+        if n > N || bs.len() < n {
+            return None;
+        }
+        let s = std::str::from_utf8(&bs[..n]).ok()?;
+        Some((arrayvec::ArrayString::from(s).ok()?, &bs[n..]))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        bs.write_bytes(self.as_bytes());
+    }
+}
+
+// Generic arrays are encoded like arrays, as their elements without a length, which is part of
+// their type.
+#[cfg(feature = "generic-array")]
+impl<T: Binary, N: generic_array::ArrayLength<T>> Binary for generic_array::GenericArray<T, N> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_generic_array(bs, T::parse)
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        parse_generic_array(bs, T::parse_strict)
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        for t in self.iter() {
+            t.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        for t in self.iter() {
+            t.unparse_canonical(bs);
+        }
+    }
+}
+
+#[cfg(feature = "generic-array")]
+impl<T: FixedSize, N: generic_array::ArrayLength<T>> FixedSize
+    for generic_array::GenericArray<T, N>
+{
+    const SIZE: usize = T::SIZE * N::USIZE;
+}
+
+#[cfg(feature = "generic-array")]
+fn parse_generic_array<T, N: generic_array::ArrayLength<T>>(
+    mut bs: &[u8],
+    parse: Parser<T>,
+) -> Option<(generic_array::GenericArray<T, N>, &[u8])> {
+    let mut v = Vec::with_capacity(N::USIZE);
+    for _i in 0..N::USIZE {
+        let (t, bs_prime) = parse(bs)?;
+        v.push(t);
+        bs = bs_prime;
+    }
+    Some((generic_array::GenericArray::from_exact_iter(v)?, bs))
+}
+
+// Bit sets are encoded as their length in bits, as a length prefix, followed by the bits, eight to
+// a byte with the lowest bit first, and parsing rejects bits set past the length.
+#[cfg(feature = "fixedbitset")]
+impl Binary for fixedbitset::FixedBitSet {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (len, bs) = parse_len(bs)?;
+        let n = len.div_ceil(8);
+        if bs.len() < n {
+            return None;
+        }
+        let mut set = fixedbitset::FixedBitSet::with_capacity(len);
+        for (i, byte) in bs[..n].iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    let index = i * 8 + bit;
+                    if index >= len {
+                        return None;
+                    }
+                    set.insert(index);
+                }
+            }
+        }
+        Some((set, &bs[n..]))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_len(self.len(), bs);
+        let mut bits = vec![0u8; self.len().div_ceil(8)];
+        for index in self.ones() {
+            bits[index / 8] |= 1 << (index % 8);
+        }
+        bs.write_bytes(&bits);
+    }
+}
+
+// Unsigned big integers are encoded as their little endian bytes, length prefixed, and parsing
+// rejects zero bytes in the most significant position, so that each value has one encoding. Zero
+// is the single byte 0.
+#[cfg(feature = "num-bigint")]
+impl Binary for num_bigint::BigUint {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        let (n, bs) = parse_len(bs)?;
+        if bs.len() < n {
+            return None;
+        }
+        let (digits, bs) = bs.split_at(n);
+        match digits {
+            [] => None,
+            [.., 0] if n > 1 => None,
+            _ => Some((num_bigint::BigUint::from_bytes_le(digits), bs)),
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        let digits = self.to_bytes_le();
+        unparse_len(digits.len(), bs);
+        bs.write_bytes(&digits);
+    }
+}
+
+// Signed big integers are encoded as a byte which is 1 for negative values and 0 otherwise,
+// followed by their magnitude, and parsing rejects negative zero.
+#[cfg(feature = "num-bigint")]
+impl Binary for num_bigint::BigInt {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        use num_bigint::{BigInt, BigUint, Sign};
+        let (negative, bs) = bool::parse(bs)?;
+        let (magnitude, bs) = BigUint::parse(bs)?;
+        let sign = match (negative, magnitude.bits()) {
+            (true, 0) => return None,
+            (true, _) => Sign::Minus,
+            (false, _) => Sign::Plus,
+        };
+        Some((BigInt::from_biguint(sign, magnitude), bs))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        (self.sign() == num_bigint::Sign::Minus).unparse(bs);
+        self.magnitude().unparse(bs);
+    }
+}
+
+// Arrays are encoded as their shape, each dimension a length prefix, followed by their elements in
+// row major order. Arrays of dynamic dimension are preceded by their number of dimensions.
+#[cfg(feature = "ndarray")]
+impl<A: Binary, D: ndarray::Dimension> Binary for ndarray::Array<A, D> {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| parse_ndarray(bs, A::parse))
+    }
+
+    fn parse_strict(bs: &[u8]) -> Option<(Self, &[u8])> {
+        nested(|| parse_ndarray(bs, A::parse_strict))
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_shape(self.shape(), D::NDIM, bs);
+        for a in self.iter() {
+            a.unparse(bs);
+        }
+    }
+
+    fn unparse_canonical<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        unparse_shape(self.shape(), D::NDIM, bs);
+        for a in self.iter() {
+            a.unparse_canonical(bs);
+        }
+    }
+}
+
+#[cfg(feature = "ndarray")]
+fn unparse_shape<S: ByteSink + ?Sized>(shape: &[usize], ndim: Option<usize>, bs: &mut S) {
+    if ndim.is_none() {
+        unparse_len(shape.len(), bs);
+    }
+    for &n in shape {
+        unparse_len(n, bs);
+    }
+}
+
+#[cfg(feature = "ndarray")]
+fn parse_ndarray<A, D: ndarray::Dimension>(
+    bs: &[u8],
+    parse: Parser<A>,
+) -> Option<(ndarray::Array<A, D>, &[u8])> {
+    let (ndim, mut bs) = match D::NDIM {
+        Some(ndim) => (ndim, bs),
+        None => parse_len(bs)?,
+    };
+    if bs.len() / LEN_PREFIX.bytes() < ndim {
+        return None;
+    }
+    let mut dim = D::zeros(ndim);
+    for n in dim.slice_mut() {
+        let (m, bs_prime) = parse_len(bs)?;
+        *n = m;
+        bs = bs_prime;
+    }
+    let mut v = Vec::new();
+    let n = dim.size_checked()?;
+    for i in 0..n {
+        let (a, bs_prime) = parse(bs)?;
+        v.push(a);
+        ensure_progress(bs, bs_prime, n - i)?;
+        bs = bs_prime;
+    }
+    Some((ndarray::Array::from_shape_vec(dim, v).ok()?, bs))
+}
+
+#[cfg(feature = "json-value")]
+impl Binary for serde_json::Value {
+    fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+        use serde_json::{Map, Number, Value};
+        let (tag, bs) = u8::parse(bs)?;
+        match tag {
+            0 => Some((Value::Null, bs)),
+            1 => {
+                let (b, bs) = bool::parse(bs)?;
+                Some((Value::Bool(b), bs))
+            }
+            2 => {
+                let (number_tag, bs) = u8::parse(bs)?;
+                match number_tag {
+                    0 => {
+                        let (n, bs) = u64::parse(bs)?;
+                        Some((Value::Number(Number::from(n)), bs))
+                    }
+                    1 => {
+                        let (n, bs) = i64::parse(bs)?;
+                        Some((Value::Number(Number::from(n)), bs))
+                    }
+                    2 => {
+                        let (f, bs) = f64::parse(bs)?;
+                        Some((Value::Number(Number::from_f64(f)?), bs))
+                    }
+                    _ => None,
+                }
+            }
+            3 => {
+                let (s, bs) = String::parse(bs)?;
+                Some((Value::String(s), bs))
+            }
+            4 => {
+                let (v, bs) = <Vec<Value> as Binary>::parse(bs)?;
+                Some((Value::Array(v), bs))
+            }
+            // objects are maps, so they count against the depth budget, and as their keys are
+            // unique, a repeated key is rejected
+            5 => nested(|| {
+                let (n, mut bs) = parse_len(bs)?;
+                let mut m = Map::new();
+                for _i in 0..n {
+                    let (k, bs_prime) = String::parse(bs)?;
+                    let (v, bs_prime) = Value::parse(bs_prime)?;
+                    if m.insert(k, v).is_some() {
+                        return None;
+                    }
+                    bs = bs_prime;
+                }
+                Some((Value::Object(m), bs))
+            }),
+            _ => None,
+        }
+    }
+
+    fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+        use serde_json::Value;
+        match self {
+            Value::Null => bs.push(0),
+            Value::Bool(b) => {
+                bs.push(1);
+                b.unparse(bs);
+            }
+            Value::Number(n) => {
+                bs.push(2);
+                if let Some(n) = n.as_u64() {
+                    bs.push(0);
+                    n.unparse(bs);
+                } else if let Some(n) = n.as_i64() {
+                    bs.push(1);
+                    n.unparse(bs);
+                } else {
+                    bs.push(2);
+                    n.as_f64()
+                        .expect("json numbers are either u64, i64, or f64")
+                        .unparse(bs);
+                }
+            }
+            Value::String(s) => {
+                bs.push(3);
+                s.unparse(bs);
+            }
+            Value::Array(v) => {
+                bs.push(4);
+                v.unparse(bs);
+            }
+            Value::Object(m) => {
+                bs.push(5);
+                unparse_len(m.len(), bs);
+                for (k, v) in m {
+                    k.unparse(bs);
+                    v.unparse(bs);
+                }
+            }
+        }
+    }
+}
+
+/// Encodes a value through its `serde::Serialize` impl in the same layout `Binary` uses, so that
+/// a type deriving both writes the same bytes either way. Structs and tuples are their fields in
+/// order, sequences, maps, and strings are length prefixed, options are preceded by a `bool`,
+/// and enum variants by their index as a `u8`.
+///
+/// Panics if the value can't be encoded, that is, if its `Serialize` impl fails or it has an
+/// enum variant past the 256th.
+#[cfg(feature = "serde")]
+pub fn to_bytes_serde<T: serde::Serialize + ?Sized>(v: &T) -> Vec<u8> {
+    let mut serializer = serde_format::Serializer { bs: Vec::new() };
+    v.serialize(&mut serializer)
+        .unwrap_or_else(|e| panic!("cannot encode value: {}", e));
+    serializer.bs
+}
+
+/// Parses bytes written by `to_bytes_serde`, or by `Binary` for a type whose `Serialize` and
+/// `Deserialize` impls agree with it, returning None unless they are consumed exactly. Like
+/// serde_json, it fails rather than nest sequences, maps, options, enums, and structs more than
+/// 128 deep, so that a hostile encoding of a recursive type can't exhaust the stack.
+#[cfg(feature = "serde")]
+pub fn from_bytes_serde<T: serde::de::DeserializeOwned>(bs: &[u8]) -> Option<T> {
+    let mut deserializer = serde_format::Deserializer::new(bs);
+    let v = T::deserialize(&mut deserializer).ok()?;
+    if deserializer.bs.is_empty() {
+        Some(v)
+    } else {
+        None
+    }
+}
+
+// A serde data format matching the layout of `Binary`.
+#[cfg(feature = "serde")]
+mod serde_format {
+    use super::{ensure_progress, parse_len, unparse_len, Binary};
+    use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+    use serde::ser::{self, Serialize};
+    use std::fmt;
+
+    #[derive(Debug)]
+    pub struct Error(String);
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(&self.0)
+        }
+    }
+
+    impl std::error::Error for Error {}
+
+    impl ser::Error for Error {
+        fn custom<T: fmt::Display>(msg: T) -> Self {
+            Error(msg.to_string())
+        }
+    }
+
+    impl de::Error for Error {
+        fn custom<T: fmt::Display>(msg: T) -> Self {
+            Error(msg.to_string())
+        }
+    }
+
+    fn variant_tag(variant_index: u32) -> Result<u8, Error> {
+        u8::try_from(variant_index).map_err(|_| Error("more than 256 variants".to_string()))
+    }
+
+    pub struct Serializer {
+        pub bs: Vec<u8>,
+    }
+
+    // Writes the elements of a sequence or map. Those whose length isn't known up front are
+    // written after `start`, and moved after their length once they are counted.
+    pub struct Compound<'a> {
+        serializer: &'a mut Serializer,
+        start: Option<usize>,
+        count: usize,
+    }
+
+    impl Compound<'_> {
+        fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.count += 1;
+            value.serialize(&mut *self.serializer)
+        }
+
+        fn finish(self) -> Result<(), Error> {
+            if let Some(start) = self.start {
+                let elements = self.serializer.bs.split_off(start);
+                unparse_len(self.count, &mut self.serializer.bs);
+                self.serializer.bs.extend_from_slice(&elements);
+            }
+            Ok(())
+        }
+    }
+
+    impl Serializer {
+        fn compound(&mut self, len: Option<usize>) -> Compound<'_> {
+            let start = match len {
+                Some(n) => {
+                    unparse_len(n, &mut self.bs);
+                    None
+                }
+                None => Some(self.bs.len()),
+            };
+            Compound {
+                serializer: self,
+                start,
+                count: 0,
+            }
+        }
+
+        fn fields(&mut self) -> Compound<'_> {
+            Compound {
+                serializer: self,
+                start: None,
+                count: 0,
+            }
+        }
+    }
+
+    impl<'a> ser::Serializer for &'a mut Serializer {
+        type Ok = ();
+        type Error = Error;
+        type SerializeSeq = Compound<'a>;
+        type SerializeTuple = Compound<'a>;
+        type SerializeTupleStruct = Compound<'a>;
+        type SerializeTupleVariant = Compound<'a>;
+        type SerializeMap = Compound<'a>;
+        type SerializeStruct = Compound<'a>;
+        type SerializeStructVariant = Compound<'a>;
+
+        fn serialize_bool(self, v: bool) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_i8(self, v: i8) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_i16(self, v: i16) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_i32(self, v: i32) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_i64(self, v: i64) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_i128(self, v: i128) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_u8(self, v: u8) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_u16(self, v: u16) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_u32(self, v: u32) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_u64(self, v: u64) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_u128(self, v: u128) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_f32(self, v: f32) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_f64(self, v: f64) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_char(self, v: char) -> Result<(), Error> {
+            v.unparse(&mut self.bs);
+            Ok(())
+        }
+
+        fn serialize_str(self, v: &str) -> Result<(), Error> {
+            self.serialize_bytes(v.as_bytes())
+        }
+
+        fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+            unparse_len(v.len(), &mut self.bs);
+            self.bs.extend_from_slice(v);
+            Ok(())
+        }
+
+        fn serialize_none(self) -> Result<(), Error> {
+            self.serialize_bool(false)
+        }
+
+        fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+            true.unparse(&mut self.bs);
+            value.serialize(self)
+        }
+
+        fn serialize_unit(self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn serialize_unit_variant(
+            self,
+            _name: &'static str,
+            variant_index: u32,
+            _variant: &'static str,
+        ) -> Result<(), Error> {
+            self.serialize_u8(variant_tag(variant_index)?)
+        }
+
+        fn serialize_newtype_struct<T: Serialize + ?Sized>(
+            self,
+            _name: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            value.serialize(self)
+        }
+
+        fn serialize_newtype_variant<T: Serialize + ?Sized>(
+            self,
+            _name: &'static str,
+            variant_index: u32,
+            _variant: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            variant_tag(variant_index)?.unparse(&mut self.bs);
+            value.serialize(self)
+        }
+
+        fn serialize_seq(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+            Ok(self.compound(len))
+        }
+
+        fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, Error> {
+            Ok(self.fields())
+        }
+
+        fn serialize_tuple_struct(
+            self,
+            _name: &'static str,
+            _len: usize,
+        ) -> Result<Compound<'a>, Error> {
+            Ok(self.fields())
+        }
+
+        fn serialize_tuple_variant(
+            self,
+            _name: &'static str,
+            variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Compound<'a>, Error> {
+            variant_tag(variant_index)?.unparse(&mut self.bs);
+            Ok(self.fields())
+        }
+
+        fn serialize_map(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+            Ok(self.compound(len))
+        }
+
+        fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Compound<'a>, Error> {
+            Ok(self.fields())
+        }
+
+        fn serialize_struct_variant(
+            self,
+            _name: &'static str,
+            variant_index: u32,
+            _variant: &'static str,
+            _len: usize,
+        ) -> Result<Compound<'a>, Error> {
+            variant_tag(variant_index)?.unparse(&mut self.bs);
+            Ok(self.fields())
+        }
+
+        fn is_human_readable(&self) -> bool {
+            false
+        }
+    }
+
+    impl ser::SerializeSeq for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTuple for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTupleStruct for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeTupleVariant for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeMap for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+            self.element(key)
+        }
+
+        fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+            value.serialize(&mut *self.serializer)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeStruct for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_field<T: Serialize + ?Sized>(
+            &mut self,
+            _key: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    impl ser::SerializeStructVariant for Compound<'_> {
+        type Ok = ();
+        type Error = Error;
+
+        fn serialize_field<T: Serialize + ?Sized>(
+            &mut self,
+            _key: &'static str,
+            value: &T,
+        ) -> Result<(), Error> {
+            self.element(value)
+        }
+
+        fn end(self) -> Result<(), Error> {
+            self.finish()
+        }
+    }
+
+    // How deeply values may nest, as in serde_json.
+    const MAX_DEPTH: usize = 128;
+
+    pub struct Deserializer<'de> {
+        pub bs: &'de [u8],
+        // how much deeper values may still nest
+        depth: usize,
+    }
+
+    impl<'de> Deserializer<'de> {
+        pub fn new(bs: &'de [u8]) -> Self {
+            Deserializer {
+                bs,
+                depth: MAX_DEPTH,
+            }
+        }
+
+        // Deserializes one level deeper, failing if that exceeds the depth limit.
+        fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+            if self.depth == 0 {
+                return Err(Error("values are nested too deeply".to_string()));
+            }
+            self.depth -= 1;
+            let result = f(self);
+            self.depth += 1;
+            result
+        }
+
+        fn take<T: Binary>(&mut self) -> Result<T, Error> {
+            let (v, bs) = T::parse(self.bs).ok_or_else(|| Error("invalid input".to_string()))?;
+            self.bs = bs;
+            Ok(v)
+        }
+
+        fn take_bytes(&mut self) -> Result<&'de [u8], Error> {
+            let (n, bs) = parse_len(self.bs).ok_or_else(|| Error("invalid length".to_string()))?;
+            if bs.len() < n {
+                return Err(Error("input ends early".to_string()));
+            }
+            let (bytes, bs) = bs.split_at(n);
+            self.bs = bs;
+            Ok(bytes)
+        }
+
+        fn take_len(&mut self) -> Result<usize, Error> {
+            let (n, bs) = parse_len(self.bs).ok_or_else(|| Error("invalid length".to_string()))?;
+            self.bs = bs;
+            Ok(n)
+        }
+    }
+
+    impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+            Err(Error(
+                "the binary format is not self describing".to_string(),
+            ))
+        }
+
+        fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_bool(self.take()?)
+        }
+
+        fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_i8(self.take()?)
+        }
+
+        fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_i16(self.take()?)
+        }
+
+        fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_i32(self.take()?)
+        }
+
+        fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_i64(self.take()?)
+        }
+
+        fn deserialize_i128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_i128(self.take()?)
+        }
+
+        fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u8(self.take()?)
+        }
+
+        fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u16(self.take()?)
+        }
+
+        fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u32(self.take()?)
+        }
+
+        fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u64(self.take()?)
+        }
+
+        fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_u128(self.take()?)
+        }
+
+        fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_f32(self.take()?)
+        }
+
+        fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_f64(self.take()?)
+        }
+
+        fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_char(self.take()?)
+        }
+
+        fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let s = std::str::from_utf8(self.take_bytes()?)
+                .map_err(|_| Error("invalid utf8".to_string()))?;
+            visitor.visit_borrowed_str(s)
+        }
+
+        fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_str(visitor)
+        }
+
+        fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_borrowed_bytes(self.take_bytes()?)
+        }
+
+        fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            self.deserialize_bytes(visitor)
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            if self.take::<bool>()? {
+                self.nested(|d| visitor.visit_some(d))
+            } else {
+                visitor.visit_none()
+            }
+        }
+
+        fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_unit_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.nested(|d| visitor.visit_newtype_struct(d))
+        }
+
+        fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let remaining = self.take_len()?;
+            self.nested(|d| {
+                visitor.visit_seq(Elements {
+                    deserializer: d,
+                    remaining,
+                    entry_start: &[],
+                })
+            })
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(
+            self,
+            len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.nested(|d| {
+                visitor.visit_seq(Elements {
+                    deserializer: d,
+                    remaining: len,
+                    entry_start: &[],
+                })
+            })
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            len: usize,
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_tuple(len, visitor)
+        }
+
+        fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let remaining = self.take_len()?;
+            self.nested(|d| {
+                visitor.visit_map(Elements {
+                    deserializer: d,
+                    remaining,
+                    entry_start: &[],
+                })
+            })
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.deserialize_tuple(fields.len(), visitor)
+        }
+
+        fn deserialize_enum<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _variants: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            self.nested(|d| visitor.visit_enum(d))
+        }
+
+        fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+            Err(Error("the binary format has no identifiers".to_string()))
+        }
+
+        fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+            Err(Error(
+                "the binary format is not self describing".to_string(),
+            ))
+        }
+
+        fn is_human_readable(&self) -> bool {
+            false
+        }
+    }
+
+    // The remaining elements of a sequence or map, or fields of a tuple or struct.
+    struct Elements<'a, 'de> {
+        deserializer: &'a mut Deserializer<'de>,
+        remaining: usize,
+        // where the map entry being read began, so that each entry must consume some input
+        entry_start: &'de [u8],
+    }
+
+    impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+        type Error = Error;
+
+        fn next_element_seed<T: DeserializeSeed<'de>>(
+            &mut self,
+            seed: T,
+        ) -> Result<Option<T::Value>, Error> {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            let before = self.deserializer.bs;
+            let element = seed.deserialize(&mut *self.deserializer)?;
+            ensure_progress(before, self.deserializer.bs, self.remaining)
+                .ok_or_else(|| Error("too many empty elements".to_string()))?;
+            self.remaining -= 1;
+            Ok(Some(element))
+        }
+
+        // never more than the input could hold, so that hostile lengths can't cause huge
+        // allocations
+        fn size_hint(&self) -> Option<usize> {
+            Some(self.remaining.min(self.deserializer.bs.len()))
+        }
+    }
+
+    impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+        type Error = Error;
+
+        fn next_key_seed<K: DeserializeSeed<'de>>(
+            &mut self,
+            seed: K,
+        ) -> Result<Option<K::Value>, Error> {
+            if self.remaining == 0 {
+                return Ok(None);
+            }
+            self.entry_start = self.deserializer.bs;
+            seed.deserialize(&mut *self.deserializer).map(Some)
+        }
+
+        fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+            let value = seed.deserialize(&mut *self.deserializer)?;
+            ensure_progress(self.entry_start, self.deserializer.bs, self.remaining)
+                .ok_or_else(|| Error("too many empty entries".to_string()))?;
+            self.remaining -= 1;
+            Ok(value)
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(self.remaining.min(self.deserializer.bs.len()))
+        }
+    }
+
+    impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
+        type Error = Error;
+        type Variant = Self;
+
+        fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+            let tag: u8 = self.take()?;
+            let variant =
+                seed.deserialize(IntoDeserializer::<Error>::into_deserializer(tag as u32))?;
+            Ok((variant, self))
+        }
+    }
+
+    impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
+        type Error = Error;
+
+        fn unit_variant(self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+            seed.deserialize(self)
+        }
+
+        fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+            de::Deserializer::deserialize_tuple(self, len, visitor)
+        }
+
+        fn struct_variant<V: Visitor<'de>>(
+            self,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value, Error> {
+            de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        cmp, derive, encode_many, iter_fixed, map_encoded, merge, parse_bytes, parse_instant,
+        parse_len, parse_schema, parse_shard, peek_tag, reframe_prefix, schema_of, shard,
+        shard_fixed, unparse_instant, unparse_len, unparse_range, BigEndian, Binary, BinaryBorrow,
+        BinaryError, BoundedVec, ByteSink, Cell, Checksummed, CommandSpec, CompactChar,
+        ControlFlow, Cow, Decoder, Degrees, DeltaKeyMap, DeltaVec, DynValue, Encode, FieldOffsets,
+        FixedBinary, FixedSize, ForVec, Instant, LengthPrefixed, LengthPrefixedOwned, Mutex,
+        OnceCell, PackedBits, Percent, PhantomData, PhantomPinned, Poll, PrefixWidth, Ragged,
+        RefCell, Reverse, RwLock, Schema, SubsecDuration, SubsecDurationError, TypeCode, VarI64,
+        VarU64, WriteSink, DEPTH_BUDGET, LEN_PREFIX, MAX_EMPTY_ELEMENTS,
+    };
+
+    use std::collections::{
+        BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, LinkedList, VecDeque,
+    };
+
+    use rand::{
+        distributions::{Alphanumeric, Distribution, Standard},
+        thread_rng,
+    };
+
+    #[cfg(feature = "bls12_381")]
+    use bls12_381::Scalar;
+
+    fn test_serialization<T>(samples: usize)
+    where
+        Standard: Distribution<T>,
+        T: Binary + PartialEq + std::fmt::Debug,
+    {
+        for _i in 0..samples {
+            let x = rand::random::<T>();
+            assert_eq!(x, T::from_bytes(&x.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_primitives_binary() {
+        let samples = 10000;
+        test_serialization::<u8>(samples);
+        test_serialization::<u16>(samples);
+        test_serialization::<u32>(samples);
+        test_serialization::<u64>(samples);
+        test_serialization::<u128>(samples);
+        test_serialization::<i8>(samples);
+        test_serialization::<i16>(samples);
+        test_serialization::<i32>(samples);
+        test_serialization::<i64>(samples);
+        test_serialization::<i128>(samples);
+        test_serialization::<f32>(samples);
+        test_serialization::<f64>(samples);
+        test_serialization::<bool>(samples);
+        test_serialization::<char>(samples);
+    }
+
+    #[test]
+    fn test_string_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let s: String = Alphanumeric
+                .sample_iter(&mut rng)
+                .take(length)
+                .map(char::from)
+                .collect();
+            assert_eq!(s, String::from_bytes(&s.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_path_binary() {
+        use std::ffi::OsString;
+        use std::path::PathBuf;
+        let p = PathBuf::from("backups/2024/archive.tar");
+        assert_eq!(p, PathBuf::from_bytes(&p.to_bytes()).unwrap());
+        let s = OsString::from("plain ascii");
+        assert_eq!(s, OsString::from_bytes(&s.to_bytes()).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_path_binary() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+        use std::path::PathBuf;
+        let s = OsString::from_vec(vec![b'a', 0xff, 0xfe, b'/', b'b']);
+        assert!(s.to_str().is_none());
+        assert_eq!(s.to_bytes(), s.clone().into_vec().to_bytes());
+        assert_eq!(s, OsString::from_bytes(&s.to_bytes()).unwrap());
+        let p = PathBuf::from(s);
+        assert_eq!(p, PathBuf::from_bytes(&p.to_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_vec_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: Vec<u8> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(v, <Vec<u8> as Binary>::from_bytes(&v.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_bounded_vec() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: Vec<u16> = Standard.sample_iter(&mut rng).take(length).collect();
+            let bv = BoundedVec::<u16, 64>::new(v.clone());
+            if length <= 64 {
+                let bv = bv.unwrap();
+                assert_eq!(bv.to_bytes(), v.to_bytes());
+                assert_eq!(bv, BoundedVec::from_bytes(&bv.to_bytes()).unwrap());
+            } else {
+                assert!(bv.is_none());
+                assert!(BoundedVec::<u16, 64>::from_bytes(&v.to_bytes()).is_none());
+            }
+        }
+        let mut hostile = Vec::new();
+        u64::MAX.unparse(&mut hostile);
+        assert!(BoundedVec::<u8, 1024>::from_bytes(&hostile).is_none());
+        // a length within a generous bound, whose elements would take terabytes
+        let mut hostile = Vec::new();
+        unparse_len(u32::MAX as usize, &mut hostile);
+        assert!(BoundedVec::<[u64; 1024], { u32::MAX as usize }>::from_bytes(&hostile).is_none());
+    }
+
+    #[test]
+    fn test_heap_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: BinaryHeap<u8> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(
+                v.clone().into_sorted_vec(),
+                <BinaryHeap<u8> as Binary>::from_bytes(&v.to_bytes())
+                    .unwrap()
+                    .into_sorted_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn test_heap_deterministic() {
+        let mut rng = thread_rng();
+        for _i in 0..100 {
+            let elements: Vec<u8> = Standard.sample_iter(&mut rng).take(100).collect();
+            let forward: BinaryHeap<u8> = elements.iter().copied().collect();
+            let backward: BinaryHeap<u8> = elements.iter().rev().copied().collect();
+            assert_eq!(forward.to_bytes(), backward.to_bytes());
+            let mut sorted = elements.clone();
+            sorted.sort_by(|a, b| b.cmp(a));
+            assert_eq!(forward.to_bytes(), sorted.to_bytes());
+        }
+    }
+
+    #[test]
+    fn test_reverse_and_ordering() {
+        let mut rng = thread_rng();
+        for _i in 0..100 {
+            let priorities: BinaryHeap<Reverse<u64>> = Standard
+                .sample_iter(&mut rng)
+                .take(50)
+                .map(Reverse)
+                .collect();
+            let bs = priorities.to_bytes();
+            assert_eq!(
+                priorities.clone().into_sorted_vec(),
+                <BinaryHeap<Reverse<u64>>>::from_bytes(&bs)
+                    .unwrap()
+                    .into_sorted_vec()
+            );
+        }
+        assert_eq!(Reverse(5u32).to_bytes(), 5u32.to_bytes());
+        for ordering in [
+            cmp::Ordering::Less,
+            cmp::Ordering::Equal,
+            cmp::Ordering::Greater,
+        ] {
+            assert_eq!(
+                cmp::Ordering::from_bytes(&ordering.to_bytes()),
+                Some(ordering)
+            );
+        }
+        assert_eq!(cmp::Ordering::Greater.to_bytes(), vec![2]);
+        assert!(cmp::Ordering::from_bytes(&[3]).is_none());
+    }
+
+    #[test]
+    fn test_btreeset_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: BTreeSet<i32> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(
+                v,
+                <BTreeSet<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_hashset_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: HashSet<i32> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(
+                v,
+                <HashSet<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unparse_range() {
+        let map: BTreeMap<u32, String> = (0..100).map(|k| (k, k.to_string())).collect();
+        let mut bs = Vec::new();
+        unparse_range(&map, 10..20, &mut bs);
+        let submap: BTreeMap<u32, String> =
+            map.range(10..20).map(|(k, v)| (*k, v.clone())).collect();
+        assert_eq!(bs, submap.to_bytes());
+        assert_eq!(BTreeMap::from_bytes(&bs), Some(submap));
+        let mut everything = Vec::new();
+        unparse_range(&map, .., &mut everything);
+        assert_eq!(everything, map.to_bytes());
+        let mut nothing = Vec::new();
+        unparse_range(&map, 200.., &mut nothing);
+        assert_eq!(nothing, BTreeMap::<u32, String>::new().to_bytes());
+    }
+
+    #[test]
+    fn test_btreemap_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let keys: Vec<u64> = Standard.sample_iter(&mut rng).take(length).collect();
+            let v: BTreeMap<u64, u128> = keys
+                .iter()
+                .copied()
+                .zip(Standard.sample_iter(&mut rng))
+                .take(length)
+                .collect();
+            assert_eq!(
+                v,
+                <BTreeMap<u64, u128> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_hashmap_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let keys: Vec<u64> = Standard.sample_iter(&mut rng).take(length).collect();
+            let v: HashMap<u64, u128> = keys
+                .iter()
+                .copied()
+                .zip(Standard.sample_iter(&mut rng))
+                .take(length)
+                .collect();
+            assert_eq!(
+                v,
+                <HashMap<u64, u128> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_large_hashmap_binary() {
+        use std::time::{Duration, Instant};
+        let m: HashMap<u64, u32> = (0..100_000u64).map(|k| (k << 32, k as u32)).collect();
+        let bs = m.to_bytes();
+        let start = Instant::now();
+        let m_prime = <HashMap<u64, u32> as Binary>::from_bytes(&bs).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(m, m_prime);
+        let s: HashSet<u64> = m.keys().copied().collect();
+        let s_prime = <HashSet<u64> as Binary>::from_bytes(&s.to_bytes()).unwrap();
+        assert_eq!(s, s_prime);
+        // a hostile length must not be trusted for the reservation
+        let mut hostile = Vec::new();
+        u64::MAX.unparse(&mut hostile);
+        assert!(<HashMap<u64, u32> as Binary>::from_bytes(&hostile).is_none());
+        assert!(<HashSet<u64> as Binary>::from_bytes(&hostile).is_none());
+
+        // every entry with the same key, which parse keeps the last of and parse_strict rejects
+        let colliding: Vec<(u64, u32)> = (0..100_000).map(|v| (7, v)).collect();
+        let bs = colliding.to_bytes();
+        let start = Instant::now();
+        let m = <HashMap<u64, u32> as Binary>::from_bytes(&bs).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(m, HashMap::from([(7, 99_999)]));
+        assert!(<HashMap<u64, u32>>::parse_strict(&bs).is_none());
+        let bs = vec![7u64; 100_000].to_bytes();
+        let s = <HashSet<u64> as Binary>::from_bytes(&bs).unwrap();
+        assert_eq!(s, HashSet::from([7]));
+        assert!(<HashSet<u64>>::parse_strict(&bs).is_none());
+    }
+
+    #[test]
+    fn test_canonical_bytes() {
+        let mut rng = thread_rng();
+        for _i in 0..100 {
+            let elements: Vec<u64> = Standard.sample_iter(&mut rng).take(50).collect();
+            let mut forward = HashSet::new();
+            for x in elements.iter() {
+                forward.insert(*x);
+            }
+            let mut backward = HashSet::with_capacity(1000);
+            for x in elements.iter().rev() {
+                backward.insert(*x);
+            }
+            assert_eq!(forward.to_canonical_bytes(), backward.to_canonical_bytes());
+            assert_eq!(
+                forward,
+                <HashSet<u64> as Binary>::from_canonical_bytes(&forward.to_canonical_bytes())
+                    .unwrap()
+            );
+
+            let forward: HashMap<u64, HashSet<u64>> =
+                elements.iter().map(|x| (*x, forward.clone())).collect();
+            let backward: HashMap<u64, HashSet<u64>> = elements
+                .iter()
+                .rev()
+                .map(|x| (*x, backward.clone()))
+                .collect();
+            let nested_forward = vec![(forward, 1u8)];
+            let nested_backward = vec![(backward, 1u8)];
+            assert_eq!(
+                nested_forward.to_canonical_bytes(),
+                nested_backward.to_canonical_bytes()
+            );
+            let bs = nested_forward.to_canonical_bytes();
+            assert_eq!(
+                nested_forward,
+                <Vec<(HashMap<u64, HashSet<u64>>, u8)> as Binary>::from_canonical_bytes(&bs)
+                    .unwrap()
+            );
+        }
+        // every NaN has the same canonical encoding
+        let quiet = f64::from_bits(0x7ff8_0000_0000_0001);
+        let negative = -f64::NAN;
+        assert_ne!(quiet.to_bytes(), negative.to_bytes());
+        assert_eq!(quiet.to_canonical_bytes(), negative.to_canonical_bytes());
+        assert_eq!(
+            vec![(f32::from_bits(0x7fc0_0001), 1u8)].to_canonical_bytes(),
+            vec![(-f32::NAN, 1u8)].to_canonical_bytes()
+        );
+        assert!(f64::from_canonical_bytes(&quiet.to_bytes()).is_none());
+        assert!(f64::from_canonical_bytes(&quiet.to_canonical_bytes())
+            .unwrap()
+            .is_nan());
+        assert_ne!(0.0f64.to_canonical_bytes(), (-0.0f64).to_canonical_bytes());
+        // everything else is canonical already
+        let example = Example { a: 1, b: 2, c: 3.0 };
+        assert_eq!(example.to_canonical_bytes(), example.to_bytes());
+        // out of order and repeated elements aren't canonical
+        let out_of_order = vec![2u8, 1].to_bytes();
+        assert!(<BTreeSet<u8> as Binary>::from_bytes(&out_of_order).is_some());
+        assert!(<BTreeSet<u8> as Binary>::from_canonical_bytes(&out_of_order).is_none());
+        assert!(<HashSet<u8> as Binary>::from_canonical_bytes(&out_of_order).is_none());
+        let repeated = vec![1u8, 1].to_bytes();
+        assert!(<HashSet<u8> as Binary>::from_canonical_bytes(&repeated).is_none());
+        let in_order = vec![1u8, 2].to_bytes();
+        assert!(<HashSet<u8> as Binary>::from_canonical_bytes(&in_order).is_some());
+    }
+
+    #[test]
+    fn test_linkedlist_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: LinkedList<i32> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(
+                v,
+                <LinkedList<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_vecdeque_binary() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: VecDeque<i32> = Standard.sample_iter(&mut rng).take(length).collect();
+            assert_eq!(
+                v,
+                <VecDeque<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_array() {
+        let mut rng = thread_rng();
+        let samples = 1000;
+        for _i in 0..samples {
+            let array: [u64; 1000] = std::array::from_fn(|_| Standard.sample(&mut rng));
+            assert_eq!(
+                array,
+                <[u64; 1000] as Binary>::from_bytes(&array.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_array_not_copy() {
+        let array = ["a", "bc", "", "def"].map(String::from);
+        let bs = array.to_bytes();
+        assert_eq!(array, <[String; 4]>::from_bytes(&bs).unwrap());
+        assert!(<[String; 4]>::from_bytes(&bs[..bs.len() - 1]).is_none());
+        assert!(<[String; 0]>::from_bytes(&[]).is_some());
+        assert_eq!(
+            <[u16; 2]>::parse_be(&[1, 2, 3, 4, 5]),
+            Some(([0x0102, 0x0304], &[5][..]))
+        );
+        assert!(<[u16; 2]>::parse_be(&[1, 2, 3]).is_none());
+
+        // the elements parsed before a failure are dropped exactly once
+        thread_local! {
+            static DROPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+        }
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.with(|drops| drops.set(drops.get() + 1));
+            }
+        }
+        impl Binary for Counted {
+            fn parse(bs: &[u8]) -> Option<(Self, &[u8])> {
+                let (b, bs) = u8::parse(bs)?;
+                if b == 0 {
+                    return None;
+                }
+                Some((Counted, bs))
+            }
+
+            fn unparse<S: ByteSink + ?Sized>(&self, bs: &mut S) {
+                bs.push(1);
+            }
+        }
+        assert!(<[Counted; 4]>::from_bytes(&[1, 1, 1, 0]).is_none());
+        assert_eq!(DROPS.with(|drops| drops.get()), 3);
+    }
+
+    #[test]
+    fn test_tuple() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let t: (u128, char) = (Standard.sample(&mut rng), Standard.sample(&mut rng));
+            assert_eq!(
+                t,
+                <(u128, char) as Binary>::from_bytes(&t.to_bytes()).unwrap()
+            );
+            let x: i128 = Standard.sample(&mut rng);
+            let t = (t.0, t.1, x);
+            assert_eq!(
+                t,
+                <(u128, char, i128) as Binary>::from_bytes(&t.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_cell() {
+        use std::cell::Cell;
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let c: Cell<u64> = Cell::new(Standard.sample(&mut rng));
+            assert_eq!(c.to_bytes(), c.get().to_bytes());
+            assert_eq!(c, <Cell<u64> as Binary>::from_bytes(&c.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_once_cell() {
+        use std::cell::OnceCell;
+        let empty: OnceCell<String> = OnceCell::new();
+        assert_eq!(empty.to_bytes(), vec![0]);
+        assert_eq!(
+            empty,
+            <OnceCell<String> as Binary>::from_bytes(&empty.to_bytes()).unwrap()
+        );
+        let full = OnceCell::from("full".to_string());
+        assert_eq!(full.to_bytes()[0], 1);
+        assert_eq!(&full.to_bytes()[1..], &"full".to_string().to_bytes()[..]);
+        assert_eq!(
+            full,
+            <OnceCell<String> as Binary>::from_bytes(&full.to_bytes()).unwrap()
+        );
+        assert!(<OnceCell<u8> as Binary>::from_bytes(&[2, 0]).is_none());
+        assert!(<OnceCell<u8> as Binary>::from_bytes(&[1]).is_none());
+    }
+
+    #[test]
+    fn test_lazy_lock() {
+        use std::sync::LazyLock;
+        let lazy = LazyLock::new(|| vec![1u16, 2, 3]);
+        assert_eq!(lazy.to_bytes(), vec![1u16, 2, 3].to_bytes());
+        assert_eq!(
+            <Vec<u16>>::from_bytes(&lazy.to_bytes()).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn test_refcell() {
+        use std::cell::RefCell;
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let length: usize = Standard.sample(&mut rng);
             let length = length % 100;
             let s: String = Alphanumeric
                 .sample_iter(&mut rng)
                 .take(length)
                 .map(char::from)
                 .collect();
-            assert_eq!(s, String::from_bytes(&s.to_bytes()).unwrap());
+            let r = RefCell::new(s);
+            assert_eq!(r.to_bytes(), r.borrow().to_bytes());
+            assert_eq!(
+                r,
+                <RefCell<String> as Binary>::from_bytes(&r.to_bytes()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot encode a RefCell while it is mutably borrowed")]
+    fn test_refcell_mutably_borrowed() {
+        let r = std::cell::RefCell::new(1u32);
+        let _guard = r.borrow_mut();
+        r.to_bytes();
+    }
+
+    #[test]
+    fn test_reference_tuple() {
+        assert_eq!((&1u32, &2u64).to_bytes(), (1u32, 2u64).to_bytes());
+        let s = "borrowed".to_string();
+        let v = vec![1i8, -1];
+        assert_eq!(
+            (&s, &v, &3u8).to_bytes(),
+            (s.clone(), v.clone(), 3u8).to_bytes()
+        );
+        assert_eq!(
+            vec![&s, &s].to_bytes(),
+            vec![s.clone(), s.clone()].to_bytes()
+        );
+        assert_eq!(
+            (Some("borrowed"), &[1u8, 2][..]).to_bytes(),
+            (Some(s), vec![1u8, 2]).to_bytes()
+        );
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(fixed_size)]
+    struct Example {
+        a: u128,
+        b: i64,
+        c: f32,
+    }
+
+    #[test]
+    fn test_field_offsets() {
+        let example = Example {
+            a: 1,
+            b: -2,
+            c: 3.0,
+        };
+        let bs = example.to_bytes();
+        let offsets = Example::field_offsets(&bs).unwrap();
+        assert_eq!(offsets, vec![("a", 0, 16), ("b", 16, 8), ("c", 24, 4)]);
+        let (_, offset, length) = offsets[1];
+        assert_eq!(i64::from_bytes(&bs[offset..offset + length]), Some(-2));
+        assert!(Example::field_offsets(&bs[..20]).is_none());
+
+        let mut trailing = bs.clone();
+        trailing.push(0xff);
+        let (ranges, rest) = Example::describe(&trailing).unwrap();
+        assert_eq!(rest, &[0xff]);
+        assert_eq!(
+            ranges,
+            vec![
+                ("a".to_string(), 0..16),
+                ("b".to_string(), 16..24),
+                ("c".to_string(), 24..28)
+            ]
+        );
+        assert_eq!(
+            ranges.iter().map(|(_, range)| range.len()).sum::<usize>(),
+            bs.len()
+        );
+        assert!(Example::describe(&bs[..27]).is_none());
+
+        let versioned = Versioned {
+            a: 7,
+            b: "seven".to_string(),
+        };
+        assert_eq!(
+            Versioned::field_offsets(&versioned.to_bytes()).unwrap(),
+            vec![("a", 6, 4), ("b", 10, versioned.b.to_bytes().len())]
+        );
+        let (ranges, _) = Versioned::describe(&versioned.to_bytes()).unwrap();
+        assert_eq!(ranges[0], ("header".to_string(), 0..6));
+        let reordered = Reordered { c: 1, a: 2, b: 3 };
+        assert_eq!(
+            Reordered::field_offsets(&reordered.to_bytes()).unwrap(),
+            vec![("a", 0, 4), ("b", 4, 2), ("c", 6, 1)]
+        );
+    }
+
+    // A type whose own items share names with those derived, which must not collide.
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct OwnSchema {
+        a: u8,
+    }
+
+    impl OwnSchema {
+        const SCHEMA: u32 = 7;
+
+        fn field_offsets(&self) -> usize {
+            self.a as usize
+        }
+
+        fn to_self_describing(&self) -> &'static str {
+            "own"
+        }
+
+        fn describe(&self) -> String {
+            format!("own {}", self.a)
+        }
+    }
+
+    #[test]
+    fn test_derived_names() {
+        let own = OwnSchema { a: 3 };
+        assert_eq!(OwnSchema::SCHEMA, 7);
+        assert_eq!(own.field_offsets(), 3);
+        assert_eq!(own.to_self_describing(), "own");
+        assert_eq!(own.describe(), "own 3");
+        let (ranges, _) = <OwnSchema as FieldOffsets>::describe(&[3]).unwrap();
+        assert_eq!(ranges, vec![("a".to_string(), 0..1)]);
+        assert_eq!(<OwnSchema as Schema>::SCHEMA, "OwnSchema { a: u8 }");
+        assert_eq!(
+            <OwnSchema as FieldOffsets>::field_offsets(&[3]),
+            Some(vec![("a", 0, 1)])
+        );
+        let bs = Schema::to_self_describing(&own);
+        assert_eq!(OwnSchema::from_self_describing(&bs), Some(own));
+    }
+
+    #[test]
+    fn test_self_describing() {
+        let example = Example {
+            a: 1,
+            b: -2,
+            c: 3.0,
+        };
+        assert_eq!(Example::SCHEMA, "Example { a: u128, b: i64, c: f32 }");
+        let bs = example.to_self_describing();
+        assert_eq!(schema_of(&bs).unwrap(), Example::SCHEMA);
+        let (_, payload) = String::parse(&bs).unwrap();
+        assert_eq!(payload, &example.to_bytes()[..]);
+        assert_eq!(example, Example::from_bytes(payload).unwrap());
+        assert_eq!(example, Example::from_self_describing(&bs).unwrap());
+        assert!(Example::from_self_describing(&example.to_bytes()).is_none());
+
+        assert_eq!(
+            WhatsIt::SCHEMA,
+            "WhatsIt { GoesEr(u128, u64), Pozer { x: f32, y: f64, z: i32 }, Whaner }"
+        );
+        assert_eq!(Reordered::SCHEMA, "Reordered { a: u32, b: u16, c: u8 }");
+        assert_eq!(Never::SCHEMA, "Never {}");
+        assert_eq!(
+            Either::<u8, Vec<String>>::SCHEMA,
+            "Either { Left(L), Right(R) }"
+        );
+        let whaner = WhatsIt::Whaner.to_self_describing();
+        assert_eq!(schema_of(&whaner).unwrap(), WhatsIt::SCHEMA);
+        assert!(Example::from_self_describing(&whaner).is_none());
+    }
+
+    #[cfg(feature = "bls12_381")]
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Nested {
+        m: BTreeMap<u64, Scalar>,
+    }
+
+    #[cfg(feature = "bls12_381")]
+    #[test]
+    fn test_nested() {
+        use ff::Field;
+        let mut rng = thread_rng();
+        let samples = 100;
+        for _i in 0..samples {
+            let mut m = BTreeMap::new();
+            for _j in 0..100 {
+                let n: u64 = Standard.sample(&mut rng);
+                let n = n % 100;
+                m.insert(n, Scalar::random(&mut rng));
+            }
+            let n = Nested { m };
+            assert_eq!(n, Nested::from_bytes(&n.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_custom_named_struct() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let example = Example {
+                a: Standard.sample(&mut rng),
+                b: Standard.sample(&mut rng),
+                c: Standard.sample(&mut rng),
+            };
+            assert_eq!(example, Example::from_bytes(&example.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reused_buffer() {
+        let mut rng = thread_rng();
+        let mut bs = Vec::new();
+        for _i in 0..100 {
+            bs.clear();
+            let examples: Vec<Example> = (0..3)
+                .map(|_| Example {
+                    a: Standard.sample(&mut rng),
+                    b: Standard.sample(&mut rng),
+                    c: Standard.sample(&mut rng),
+                })
+                .collect();
+            for example in &examples {
+                example.unparse(&mut bs);
+            }
+            let mut rest = &bs[..];
+            for example in &examples {
+                let (parsed, rest_prime) = Example::parse(rest).unwrap();
+                assert_eq!(example, &parsed);
+                rest = rest_prime;
+            }
+            assert!(rest.is_empty());
+
+            bs.clear();
+            encode_many(&examples, &mut bs);
+            assert_eq!(bs, examples.to_bytes());
+            assert_eq!(examples, <Vec<Example> as Binary>::from_bytes(&bs).unwrap());
+        }
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Other(u128, i64);
+
+    #[test]
+    fn test_custom_unnamed_struct() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let other = Other(Standard.sample(&mut rng), Standard.sample(&mut rng));
+            assert_eq!(other, Other::from_bytes(&other.to_bytes()).unwrap());
+        }
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum WhatsIt {
+        GoesEr(u128, u64),
+        Pozer { x: f32, y: f64, z: i32 },
+        Whaner,
+    }
+
+    #[test]
+    fn test_custom_enum() {
+        let mut rng = thread_rng();
+        let samples = 10000;
+        for _i in 0..samples {
+            let choice: u8 = Standard.sample(&mut rng);
+            let choice = choice % 3;
+            let whatsit = if choice == 0 {
+                WhatsIt::GoesEr(Standard.sample(&mut rng), Standard.sample(&mut rng))
+            } else if choice == 1 {
+                WhatsIt::Pozer {
+                    x: Standard.sample(&mut rng),
+                    y: Standard.sample(&mut rng),
+                    z: Standard.sample(&mut rng),
+                }
+            } else {
+                WhatsIt::Whaner
+            };
+            assert_eq!(whatsit, WhatsIt::from_bytes(&whatsit.to_bytes()).unwrap());
+        }
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(reserved = 200..256)]
+    enum Extensible {
+        Known(u8),
+        AlsoKnown,
+    }
+
+    #[test]
+    fn test_reserved_tags() {
+        let known = Extensible::Known(3);
+        let bs = known.to_bytes();
+        let (parsed, rest) = Extensible::try_parse(&bs).unwrap();
+        assert_eq!(parsed, known);
+        assert!(rest.is_empty());
+        assert_eq!(
+            Extensible::try_parse(&[210, 1, 2]),
+            Err(BinaryError::ReservedTag(210))
+        );
+        assert_eq!(
+            Extensible::try_parse(&[255]),
+            Err(BinaryError::ReservedTag(255))
+        );
+        assert!(Extensible::parse(&[210, 1, 2]).is_none());
+        assert_eq!(Extensible::try_parse(&[2]), Err(BinaryError::Invalid));
+        assert_eq!(Extensible::try_parse(&[199]), Err(BinaryError::Invalid));
+        assert_eq!(Extensible::try_parse(&[0]), Err(BinaryError::Invalid));
+        assert_eq!(Extensible::try_parse(&[]), Err(BinaryError::Invalid));
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Message {
+        Ping(u32),
+        Text(String),
+        #[binary(unknown)]
+        Unknown(u8, Vec<u8>),
+    }
+
+    #[test]
+    fn test_unknown_variant() {
+        let ping = Message::Ping(7);
+        assert_eq!(ping.to_bytes()[0], 0);
+        assert_eq!(ping, Message::from_bytes(&ping.to_bytes()).unwrap());
+        let text = Message::Text("hi".to_string());
+        assert_eq!(text, Message::from_bytes(&text.to_bytes()).unwrap());
+        // a message from a newer peer with a variant we don't know about
+        let newer = [5, 1, 2, 3];
+        let (unknown, rest) = Message::parse(&newer).unwrap();
+        assert_eq!(unknown, Message::Unknown(5, vec![1, 2, 3]));
+        assert!(rest.is_empty());
+        assert_eq!(unknown.to_bytes(), newer);
+        assert_eq!(
+            Message::from_bytes(&[2]).unwrap(),
+            Message::Unknown(2, vec![])
+        );
+        // known tags still have to parse
+        assert!(Message::from_bytes(&[1, 0]).is_none());
+        assert!(Message::from_bytes(&[]).is_none());
+    }
+
+    #[test]
+    fn test_peek_tag() {
+        let goes_er = WhatsIt::GoesEr(1, 2).to_bytes();
+        assert_eq!(peek_tag(&goes_er), Some(0));
+        let pozer = WhatsIt::Pozer {
+            x: 1.0,
+            y: 2.0,
+            z: 3,
+        }
+        .to_bytes();
+        assert_eq!(peek_tag(&pozer), Some(1));
+        let whaner = WhatsIt::Whaner.to_bytes();
+        assert_eq!(peek_tag(&whaner), Some(2));
+        assert_eq!(peek_tag(&[]), None);
+        // peeking doesn't consume anything
+        assert_eq!(WhatsIt::Whaner, WhatsIt::from_bytes(&whaner).unwrap());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq, Clone, Copy)]
+    #[binary(repr_tag)]
+    #[repr(u16)]
+    enum Opcode {
+        Nop,
+        Load = 0x0102,
+        Store,
+        Halt = 0xFFFF,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(repr_tag)]
+    #[repr(i8)]
+    enum Step {
+        Down(u8) = -1,
+        Flat,
+        Up { by: u16 } = 5,
+    }
+
+    #[test]
+    fn test_repr_enum() {
+        assert_eq!(Opcode::Load.to_bytes(), vec![0x02, 0x01]);
+        for op in [Opcode::Nop, Opcode::Load, Opcode::Store, Opcode::Halt] {
+            let bs = op.to_bytes();
+            assert_eq!(bs, (op as u16).to_bytes());
+            assert_eq!(Opcode::from_bytes(&bs), Some(op));
+        }
+        assert!(Opcode::from_bytes(&[1, 0]).is_none());
+        assert!(Opcode::from_bytes(&[0]).is_none());
+        assert_eq!(Step::Down(3).to_bytes(), vec![0xff, 3]);
+        assert_eq!(Step::Flat.to_bytes(), vec![0]);
+        assert_eq!(Step::Up { by: 2 }.to_bytes(), vec![5, 2, 0]);
+        for step in [Step::Down(3), Step::Flat, Step::Up { by: 2 }] {
+            assert_eq!(Step::from_bytes(&step.to_bytes()), Some(step));
+        }
+        assert!(Step::from_bytes(&[1]).is_none());
+        assert_eq!(
+            Opcode::SCHEMA,
+            "Opcode: u16 { Nop = 0, Load = 258, Store = 259, Halt = 65535 }"
+        );
+        assert_eq!(
+            Step::SCHEMA,
+            "Step: i8 { Down(u8) = -1, Flat = 0, Up { by: u16 } = 5 }"
+        );
+        // without repr_tag, the repr leaves the encoding alone
+        assert_eq!(Level::High.to_bytes(), vec![1]);
+        assert_eq!(Level::from_bytes(&[1]), Some(Level::High));
+        assert_eq!(Level::SCHEMA, "Level { Low, High }");
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[repr(u8)]
+    enum Level {
+        Low = 10,
+        High = 20,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(magic = 0xFEEDF00D, version = 1, fixed_size)]
+    struct Stamped {
+        at: u64,
+    }
+
+    #[test]
+    fn test_fixed_size() {
+        assert_eq!(<Example as FixedBinary>::LEN, 28);
+        let example = Example { a: 1, b: 2, c: 3.0 };
+        assert_eq!(example.to_bytes().len(), Example::LEN);
+        let mut buffer = [0; <Example as FixedBinary>::LEN];
+        buffer.copy_from_slice(&example.to_bytes());
+        assert_eq!(Example::from_bytes(&buffer), Some(example));
+        assert_eq!(<Header as FixedSize>::SIZE, 4 + 4 + 10 + 1);
+        assert_eq!(<Stamped as FixedBinary>::LEN, 14);
+        assert_eq!(Stamped { at: 5 }.to_bytes().len(), Stamped::LEN);
+    }
+
+    const _: () = assert!(<[[u8; 4]; 8] as FixedBinary>::LEN == 32);
+
+    #[test]
+    fn test_nested_arrays() {
+        let mut x = [[0u8; 4]; 8];
+        for (i, row) in x.iter_mut().enumerate() {
+            for (j, b) in row.iter_mut().enumerate() {
+                *b = (i * 4 + j) as u8;
+            }
+        }
+        let bs = x.to_bytes();
+        assert_eq!(bs.len(), 32);
+        assert_eq!(bs, (0..32).collect::<Vec<u8>>());
+        assert_eq!(<[[u8; 4]; 8]>::from_bytes(&bs), Some(x));
+        let y = [[[1u16, 2], [3, 4]], [[5, 6], [7, 8]]];
+        assert_eq!(y.to_bytes().len(), <[[[u16; 2]; 2]; 2] as FixedBinary>::LEN);
+        assert_eq!(<[[[u16; 2]; 2]; 2]>::from_bytes(&y.to_bytes()), Some(y));
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(borrow)]
+    struct Msg<'a> {
+        id: u32,
+        name: &'a str,
+        payload: &'a [u8],
+        tags: Vec<&'a str>,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct OwnedMsg {
+        id: u32,
+        name: String,
+        payload: Vec<u8>,
+        tags: Vec<String>,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(borrow, magic = 0xB0770E)]
+    struct Note<'a>(&'a str, Option<&'a [u8]>);
+
+    #[test]
+    fn test_borrowed() {
+        let msg = Msg {
+            id: 7,
+            name: "greeting",
+            payload: b"hello",
+            tags: vec!["a", "bc"],
+        };
+        let bs = msg.to_bytes();
+        let owned = OwnedMsg {
+            id: 7,
+            name: "greeting".to_string(),
+            payload: b"hello".to_vec(),
+            tags: vec!["a".to_string(), "bc".to_string()],
+        };
+        assert_eq!(bs, owned.to_bytes());
+        let decoded = Msg::from_bytes_borrowed(&bs).unwrap();
+        assert_eq!(decoded, msg);
+        let input = bs.as_ptr_range();
+        assert!(input.contains(&decoded.name.as_ptr()));
+        assert!(input.contains(&decoded.payload.as_ptr()));
+        assert!(input.contains(&decoded.tags[1].as_ptr()));
+        assert_eq!(Msg::from_bytes_borrowed(&bs[..bs.len() - 1]), None);
+        let mut invalid = owned;
+        invalid.name = "\u{e9}".to_string();
+        let mut invalid = invalid.to_bytes();
+        let lead = invalid.iter().position(|b| *b == 0xC3).unwrap();
+        invalid[lead] = 0xFF;
+        assert_eq!(Msg::from_bytes_borrowed(&invalid), None);
+
+        for note in [Note("plain", None), Note("attached", Some(&[1, 2, 3]))] {
+            let bs = note.to_bytes();
+            assert_eq!(&bs[..4], &0xB0770Eu32.to_le_bytes());
+            assert_eq!(Note::from_bytes_borrowed(&bs), Some(note));
+        }
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(magic = 0xCAFEBABE, version = 3)]
+    struct Versioned {
+        a: u32,
+        b: String,
+    }
+
+    #[test]
+    fn test_versioned_struct() {
+        let v = Versioned {
+            a: 7,
+            b: "seven".to_string(),
+        };
+        let bs = v.to_bytes();
+        assert_eq!(&bs[0..4], &0xCAFEBABEu32.to_le_bytes());
+        assert_eq!(&bs[4..6], &3u16.to_le_bytes());
+        assert_eq!(v, Versioned::from_bytes(&bs).unwrap());
+
+        let mut wrong_magic = bs.clone();
+        wrong_magic[0] ^= 1;
+        assert!(Versioned::from_bytes(&wrong_magic).is_none());
+
+        let mut older_version = bs.clone();
+        older_version[4..6].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(v, Versioned::from_bytes(&older_version).unwrap());
+
+        let mut future_version = bs.clone();
+        future_version[4..6].copy_from_slice(&4u16.to_le_bytes());
+        assert!(Versioned::from_bytes(&future_version).is_none());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Reordered {
+        #[binary(order = 2)]
+        c: u8,
+        #[binary(order = 0)]
+        a: u32,
+        #[binary(order = 1)]
+        b: u16,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum ReorderedEnum {
+        Tuple(#[binary(order = 1)] u8, #[binary(order = 0)] u16),
+    }
+
+    #[test]
+    fn test_field_order() {
+        let r = Reordered {
+            c: 0x07,
+            a: 0x04030201,
+            b: 0x0605,
+        };
+        let bs = r.to_bytes();
+        assert_eq!(bs, vec![0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]);
+        assert_eq!(r, Reordered::from_bytes(&bs).unwrap());
+
+        let e = ReorderedEnum::Tuple(0x03, 0x0201);
+        let bs = e.to_bytes();
+        assert_eq!(bs, vec![0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(e, ReorderedEnum::from_bytes(&bs).unwrap());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct RecordV1 {
+        id: u32,
+        name: String,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct RecordV2 {
+        id: u32,
+        name: String,
+        #[binary(default)]
+        tags: Vec<String>,
+        #[binary(default)]
+        score: u16,
+    }
+
+    #[test]
+    fn test_default_fields() {
+        let old = RecordV1 {
+            id: 1,
+            name: "old".to_string(),
+        };
+        let bs = old.to_bytes();
+        let upgraded = RecordV2::from_bytes(&bs).unwrap();
+        assert_eq!(
+            upgraded,
+            RecordV2 {
+                id: 1,
+                name: "old".to_string(),
+                tags: Vec::new(),
+                score: 0,
+            }
+        );
+        assert_eq!(RecordV2::field_offsets(&bs).unwrap().len(), 2);
+        let new = RecordV2 {
+            id: 2,
+            name: "new".to_string(),
+            tags: vec!["tagged".to_string()],
+            score: 7,
+        };
+        assert_eq!(RecordV2::from_bytes(&new.to_bytes()), Some(new));
+        // the fields before the defaulted ones are still required
+        assert!(RecordV2::from_bytes(&bs[..bs.len() - 1]).is_none());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian, fixed_size)]
+    struct Header {
+        length: u32,
+        checksum: [u16; 2],
+        point: Point,
+        flag: bool,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian, fixed_size)]
+    struct Point(i16, f64);
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian)]
+    struct Marker;
+
+    #[test]
+    fn test_both_endian() {
+        let header = Header {
+            length: 0x01020304,
+            checksum: [0x0506, 0x0708],
+            point: Point(-2, 1.5),
+            flag: true,
+        };
+        let mut le = Vec::new();
+        header.unparse_le(&mut le);
+        assert_eq!(le, header.to_bytes());
+        let mut be = Vec::new();
+        header.unparse_be(&mut be);
+        assert_ne!(be, le);
+        assert_eq!(&be[..8], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(&be[8..10], &(-2i16).to_be_bytes());
+        assert_eq!(&be[10..18], &1.5f64.to_be_bytes());
+        assert_eq!(be.len(), le.len());
+        assert_eq!(Header::parse_be(&be), Some((header, &[][..])));
+        assert_eq!(Header::parse_le(&le).unwrap().0.length, 0x01020304);
+        assert!(Header::parse_be(&be[..be.len() - 1]).is_none());
+        let mut marker = Vec::new();
+        Marker.unparse_be(&mut marker);
+        assert!(marker.is_empty());
+        assert_eq!(Marker::parse_be(&[]), Some((Marker, &[][..])));
+    }
+
+    // a unit of measure, which is never encoded
+    #[derive(Debug, PartialEq)]
+    struct Meters;
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Measured<#[binary(no_bound)] Unit> {
+        value: u64,
+        unit: PhantomData<Unit>,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(bound = "T: Binary")]
+    struct Wrapper<T, U> {
+        data: Vec<T>,
+        _marker: PhantomData<fn() -> U>,
+    }
+
+    #[test]
+    fn test_bounds() {
+        let distance: Measured<Meters> = Measured {
+            value: 42,
+            unit: PhantomData,
+        };
+        let bs = distance.to_bytes();
+        assert_eq!(bs, 42u64.to_bytes());
+        assert_eq!(Measured::from_bytes(&bs), Some(distance));
+        let wrapper: Wrapper<u16, Meters> = Wrapper {
+            data: vec![1, 2, 3],
+            _marker: PhantomData,
+        };
+        let bs = wrapper.to_bytes();
+        assert_eq!(bs, vec![1u16, 2, 3].to_bytes());
+        assert_eq!(
+            Wrapper::<u16, Meters>::from_bytes(&bs).unwrap().data,
+            wrapper.data
+        );
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Pinned {
+        value: u32,
+        _pin: PhantomPinned,
+    }
+
+    #[test]
+    fn test_phantom_pinned() {
+        let pinned = Pinned {
+            value: 9,
+            _pin: PhantomPinned,
+        };
+        let bs = pinned.to_bytes();
+        assert_eq!(bs, 9u32.to_bytes());
+        assert_eq!(Pinned::from_bytes(&bs), Some(pinned));
+        assert_eq!(<PhantomPinned as FixedSize>::SIZE, 0);
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Node {
+        Leaf(u64),
+        Branch {
+            left: Option<Box<Node>>,
+            right: Option<Box<Node>>,
+        },
+    }
+
+    fn random_node<R: rand::Rng>(rng: &mut R, depth: usize) -> Node {
+        if depth == 0 || rng.gen_ratio(1, 4) {
+            return Node::Leaf(rng.gen());
+        }
+        let child = |rng: &mut R| {
+            rng.gen::<bool>()
+                .then(|| Box::new(random_node(rng, depth - 1)))
+        };
+        Node::Branch {
+            left: child(rng),
+            right: child(rng),
+        }
+    }
+
+    #[test]
+    fn test_option_box_tree() {
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let tree = random_node(&mut rng, 8);
+            assert_eq!(Node::from_bytes(&tree.to_bytes()), Some(tree));
+        }
+        let mut deep = Node::Leaf(0);
+        for _i in 0..500 {
+            deep = Node::Branch {
+                left: Some(Box::new(deep)),
+                right: None,
+            };
+        }
+        assert_eq!(Node::from_bytes(&deep.to_bytes()), Some(deep));
+        assert!(<Option<u8>>::from_bytes(&[2, 0]).is_none());
+        assert_eq!(None::<u32>.to_bytes(), vec![0]);
+        assert_eq!(Some(7u8).to_bytes(), vec![1, 7]);
+        let borrowed: Cow<[u16]> = Cow::Borrowed(&[1, 2, 3]);
+        let bs = borrowed.to_bytes();
+        assert_eq!(bs, vec![1u16, 2, 3].to_bytes());
+        assert_eq!(<Cow<[u16]>>::from_bytes(&bs), Some(borrowed));
+    }
+
+    #[test]
+    fn test_option_nonzero() {
+        use std::num::{NonZeroI64, NonZeroU32, NonZeroU8};
+        assert_eq!(None::<NonZeroU32>.to_bytes(), vec![0; 4]);
+        assert_eq!(None::<NonZeroI64>.to_bytes(), vec![0; 8]);
+        assert_eq!(NonZeroU32::new(7).to_bytes(), 7u32.to_bytes());
+        for n in [0, 1, 255] {
+            let x = NonZeroU8::new(n);
+            assert_eq!(x.to_bytes(), vec![n]);
+            assert_eq!(<Option<NonZeroU8>>::from_bytes(&x.to_bytes()), Some(x));
+        }
+        for n in [i64::MIN, -1, 0, 1, i64::MAX] {
+            let x = NonZeroI64::new(n);
+            assert_eq!(<Option<NonZeroI64>>::from_bytes(&x.to_bytes()), Some(x));
+        }
+        assert_eq!(<Option<NonZeroU32> as FixedSize>::SIZE, 4);
+        assert!(<Option<NonZeroU32>>::from_bytes(&[1, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn test_control_flow_poll() {
+        let flows: [ControlFlow<String, u16>; 2] = [
+            ControlFlow::Continue(7),
+            ControlFlow::Break("done".to_string()),
+        ];
+        for flow in flows {
+            assert_eq!(ControlFlow::from_bytes(&flow.to_bytes()), Some(flow));
+        }
+        assert_eq!(ControlFlow::<u8, u8>::Continue(3).to_bytes(), vec![0, 3]);
+        assert_eq!(ControlFlow::<u8, u8>::Break(4).to_bytes(), vec![1, 4]);
+        assert!(<ControlFlow<u8, u8>>::from_bytes(&[2, 4]).is_none());
+        for poll in [Poll::Pending, Poll::Ready(-9i32)] {
+            assert_eq!(Poll::from_bytes(&poll.to_bytes()), Some(poll));
+        }
+        assert_eq!(Poll::<u8>::Pending.to_bytes(), vec![0]);
+        assert_eq!(Poll::Ready(5u8).to_bytes(), vec![1, 5]);
+        assert!(<Poll<u8>>::from_bytes(&[2, 5]).is_none());
+        assert!(<Poll<u8>>::from_bytes(&[1]).is_none());
+    }
+
+    #[test]
+    fn test_boxed_slices() {
+        let slice: Box<[u8]> = Box::new([1, 2, 3]);
+        let bs = slice.to_bytes();
+        assert_eq!(bs, vec![1u8, 2, 3].to_bytes());
+        assert_eq!(<Box<[u8]>>::from_bytes(&bs), Some(slice));
+        assert_eq!(<Vec<u8>>::from_bytes(&bs), Some(vec![1, 2, 3]));
+        let empty: Box<[String]> = Box::new([]);
+        assert_eq!(<Box<[String]>>::from_bytes(&empty.to_bytes()), Some(empty));
+        let s: Box<str> = "boxed".into();
+        let bs = s.to_bytes();
+        assert_eq!(bs, "boxed".to_string().to_bytes());
+        assert_eq!(<Box<str>>::from_bytes(&bs), Some(s));
+        assert!(<Box<str>>::from_bytes(&vec![0xffu8].to_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_empty_elements() {
+        let start = std::time::Instant::now();
+        assert!(<Vec<()>>::parse(&[0xff; 8]).is_none());
+        assert!(<HashSet<()>>::parse(&[0xff; 8]).is_none());
+        assert!(<BTreeMap<(), ()>>::parse(&[0xff; 8]).is_none());
+        assert!(<Vec<Vec<()>>>::parse(&[&[1][..], &[0xff; 8]].concat()).is_none());
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        let empties = vec![(); MAX_EMPTY_ELEMENTS];
+        assert_eq!(<Vec<()>>::from_bytes(&empties.to_bytes()), Some(empties));
+        let mut too_many = Vec::new();
+        unparse_len(MAX_EMPTY_ELEMENTS + 1, &mut too_many);
+        assert!(<Vec<()>>::from_bytes(&too_many).is_none());
+        assert!(<Vec<EmptyUnit>>::from_bytes(&too_many).is_none());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(fixed_size)]
+    struct Presence {
+        present: u8,
+        marker: (),
+        nested: ((), [(); 4]),
+        phantom: PhantomData<u64>,
+    }
+
+    #[test]
+    fn test_unit_elision() {
+        let presence = Presence {
+            present: 1,
+            marker: (),
+            nested: ((), [(); 4]),
+            phantom: PhantomData,
+        };
+        assert_eq!(presence.to_bytes(), vec![1]);
+        assert_eq!(<Presence as FixedBinary>::LEN, 1);
+        assert_eq!(Presence::from_bytes(&[1]), Some(presence));
+        let prefix = Vec::<()>::new().to_bytes().len();
+        for n in [0, 1, 1000, MAX_EMPTY_ELEMENTS, MAX_EMPTY_ELEMENTS + 1] {
+            let units = vec![(); n];
+            let bs = units.to_bytes();
+            assert_eq!(bs.len(), prefix);
+            let parsed = <Vec<()>>::from_bytes(&bs);
+            if n <= MAX_EMPTY_ELEMENTS {
+                assert_eq!(parsed, Some(units));
+            } else {
+                assert_eq!(parsed, None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_hasher() {
+        use std::hash::BuildHasherDefault;
+        // a hasher other than the default, keeping only the last eight bytes written
+        #[derive(Default)]
+        struct LastBytes(u64);
+        impl std::hash::Hasher for LastBytes {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+            fn write(&mut self, bytes: &[u8]) {
+                for b in bytes {
+                    self.0 = self.0 << 8 | *b as u64;
+                }
+            }
+        }
+        type Build = BuildHasherDefault<LastBytes>;
+        let mut m: HashMap<u32, String, Build> = HashMap::default();
+        m.insert(1, "one".to_string());
+        m.insert(2, "two".to_string());
+        let bs = m.to_bytes();
+        assert_eq!(
+            <HashMap<u32, String, Build>>::from_bytes(&bs),
+            Some(m.clone())
+        );
+        assert_eq!(
+            <HashMap<u32, String>>::from_bytes(&bs),
+            Some(m.into_iter().collect())
+        );
+        let s: HashSet<u8, Build> = [3, 1, 2].into_iter().collect();
+        assert_eq!(
+            <HashSet<u8, Build>>::from_bytes(&s.to_bytes()),
+            Some(s.clone())
+        );
+        assert_eq!(
+            <HashSet<u8, Build>>::parse_strict(&s.to_bytes()),
+            Some((s, &[][..]))
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        type Config = HashMap<String, HashMap<String, Vec<String>>>;
+        let config: Config = HashMap::from([(
+            "server".to_string(),
+            HashMap::from([(
+                "hosts".to_string(),
+                vec!["a.example".to_string(), "b.example".to_string()],
+            )]),
+        )]);
+        let bs = config.to_bytes();
+        // the strings are collections of bytes, so they count as a level as well
+        assert_eq!(Config::parse_depth_limited(&bs, 4), Some((config, &[][..])));
+        assert!(Config::parse_depth_limited(&bs, 3).is_none());
+
+        // a chain of left branches far too deep to parse without a limit
+        let depth = 1_000_000;
+        let mut deep = Vec::new();
+        for _i in 0..depth {
+            deep.extend_from_slice(&[1, 1]);
+        }
+        Node::Leaf(7).unparse(&mut deep);
+        deep.resize(deep.len() + depth, 0);
+        assert!(Node::parse_depth_limited(&deep, 1000).is_none());
+        // the budget is restored afterward
+        assert!(Node::parse(&Node::Leaf(7).to_bytes()).is_some());
+        let shallow = Node::Branch {
+            left: Some(Box::new(Node::Leaf(1))),
+            right: None,
+        };
+        assert_eq!(
+            Node::parse_depth_limited(&shallow.to_bytes(), 1),
+            Some((shallow, &[][..]))
+        );
+        assert!(Node::parse_depth_limited(&Node::Leaf(0).to_bytes(), 0).is_some());
+
+        // the budget is restored even if parsing panics
+        struct Explosive;
+        impl Binary for Explosive {
+            fn parse(_bs: &[u8]) -> Option<(Self, &[u8])> {
+                panic!("explosive")
+            }
+
+            fn unparse<S: ByteSink + ?Sized>(&self, _bs: &mut S) {}
         }
+        let one = vec![vec![()]].to_bytes();
+        let caught =
+            std::panic::catch_unwind(|| <Vec<Vec<Explosive>>>::parse_depth_limited(&one, 8));
+        assert!(caught.is_err());
+        assert_eq!(DEPTH_BUDGET.get(), None);
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct Index {
+        name: String,
+        entries: Vec<BTreeMap<u8, bool>>,
+    }
+
+    #[test]
+    fn test_parse_strict() {
+        assert!(bool::parse_strict(&[2]).is_none());
+        assert_eq!(bool::parse_strict(&[1]), Some((true, &[][..])));
+        assert!(ReorderedEnum::parse_strict(&[1, 0, 0, 0]).is_none());
+        let e = ReorderedEnum::Tuple(3, 4);
+        assert_eq!(
+            ReorderedEnum::parse_strict(&e.to_bytes()),
+            Some((e, &[][..]))
+        );
+
+        let index = Index {
+            name: "strict".to_string(),
+            entries: vec![BTreeMap::from([(1, true), (2, false)]), BTreeMap::new()],
+        };
+        // maps are encoded like a `Vec` of their entries
+        let mut swapped = Vec::new();
+        index.name.unparse(&mut swapped);
+        vec![vec![(2u8, false), (1u8, true)], vec![]].unparse(&mut swapped);
+        assert_eq!(
+            Index::parse_strict(&index.to_bytes()),
+            Some((index, &[][..]))
+        );
+        // keys out of order, which parse accepts but unparse never writes
+        assert!(Index::parse(&swapped).is_some());
+        assert!(Index::parse_strict(&swapped).is_none());
+
+        let repeated = vec![(1u8, 2u8), (1, 3)].to_bytes();
+        assert!(<BTreeMap<u8, u8>>::parse(&repeated).is_some());
+        assert!(<BTreeMap<u8, u8>>::parse_strict(&repeated).is_none());
+        assert!(<HashMap<u8, u8>>::parse_strict(&repeated).is_none());
+        let repeated = vec![5u8, 5].to_bytes();
+        assert!(<HashSet<u8>>::parse_strict(&repeated).is_none());
+        assert!(<BTreeSet<u8>>::parse_strict(&repeated).is_none());
+        assert!(<Option<Box<[bool; 2]>>>::parse_strict(&[1, 1, 2]).is_none());
+
+        // collections and wrappers forward strictness to their elements
+        let swapped = vec![vec![(2u8, 0u8), (1, 0)]].to_bytes();
+        assert!(<VecDeque<BTreeMap<u8, u8>>>::parse(&swapped).is_some());
+        assert!(<VecDeque<BTreeMap<u8, u8>>>::parse_strict(&swapped).is_none());
+        assert!(<LinkedList<BTreeMap<u8, u8>>>::parse_strict(&swapped).is_none());
+        assert!(<Cow<[BTreeMap<u8, u8>]>>::parse_strict(&swapped).is_none());
+        assert!(<BoundedVec<BTreeMap<u8, u8>, 4>>::parse_strict(&swapped).is_none());
+        assert!(<LengthPrefixedOwned<BTreeMap<u8, u8>>>::parse_strict(&swapped).is_none());
+        assert!(<Reverse<bool>>::parse_strict(&[2]).is_none());
+        assert!(<Cell<bool>>::parse_strict(&[2]).is_none());
+        assert!(<RefCell<bool>>::parse_strict(&[2]).is_none());
+        assert!(<Mutex<bool>>::parse_strict(&[2]).is_none());
+        assert!(<RwLock<bool>>::parse_strict(&[2]).is_none());
+        assert!(<OnceCell<bool>>::parse_strict(&[1, 2]).is_none());
+        assert!(<DeltaKeyMap<bool>>::parse_strict(
+            &DeltaKeyMap(BTreeMap::from([(3, true)])).to_bytes()
+        )
+        .is_some());
+        let ragged = Ragged(vec![vec![true], vec![]]).to_bytes();
+        let mut invalid = ragged.clone();
+        *invalid.last_mut().unwrap() = 2;
+        assert!(<Ragged<bool>>::parse_strict(&ragged).is_some());
+        assert!(<Ragged<bool>>::parse_strict(&invalid).is_none());
+
+        // a heap is written largest first, so ascending input is non-canonical
+        let ascending = vec![1u8, 2, 3].to_bytes();
+        assert!(<BinaryHeap<u8>>::parse(&ascending).is_some());
+        assert!(<BinaryHeap<u8>>::parse_strict(&ascending).is_none());
+        let heap = BinaryHeap::from(vec![1u8, 3, 2, 3]);
+        let bytes = heap.to_bytes();
+        let (parsed, rest) = <BinaryHeap<u8>>::parse_strict(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.into_sorted_vec(), vec![1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn test_byte_vec() {
+        let mut rng = thread_rng();
+        let blob: Vec<u8> = Standard.sample_iter(&mut rng).take(1 << 20).collect();
+        let bs = blob.to_bytes();
+        let mut expected = Vec::new();
+        unparse_len(blob.len(), &mut expected);
+        expected.extend_from_slice(&blob);
+        assert_eq!(bs, expected);
+        assert_eq!(<Vec<u8>>::parse(&bs), <Vec<u8>>::parse_strict(&bs));
+        assert_eq!(<Vec<u8>>::from_bytes(&bs), Some(blob));
+        assert_eq!(<Vec<u8>>::parse(&bs[..bs.len() - 1]), None);
+        let followed = [vec![1u8, 2, 3].to_bytes(), vec![9]].concat();
+        assert_eq!(<Vec<u8>>::parse(&followed), Some((vec![1, 2, 3], &[9][..])));
+    }
+
+    #[test]
+    fn test_btree_map_key_order() {
+        let ascending = vec![(1u8, 'a'), (2, 'b'), (7, 'c')].to_bytes();
+        let map = BTreeMap::from([(1u8, 'a'), (2, 'b'), (7, 'c')]);
+        assert_eq!(map.to_bytes(), ascending);
+        assert_eq!(
+            <BTreeMap<u8, char>>::parse_strict(&ascending),
+            Some((map, &[][..]))
+        );
+        let duplicate = vec![(1u8, 'a'), (2, 'b'), (2, 'c')].to_bytes();
+        assert_eq!(<BTreeMap<u8, char>>::parse(&duplicate).unwrap().0[&2], 'c');
+        assert!(<BTreeMap<u8, char>>::parse_strict(&duplicate).is_none());
+        let out_of_order = vec![(1u8, 'a'), (7, 'c'), (2, 'b')].to_bytes();
+        assert!(<BTreeMap<u8, char>>::parse(&out_of_order).is_some());
+        assert!(<BTreeMap<u8, char>>::parse_strict(&out_of_order).is_none());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct MessageHeader {
+        id: u32,
+        kind: u8,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct FlatMessage {
+        #[binary(flatten)]
+        header: MessageHeader,
+        body: String,
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    struct InlineMessage {
+        id: u32,
+        kind: u8,
+        body: String,
+    }
+
+    #[test]
+    fn test_flatten() {
+        let message = FlatMessage {
+            header: MessageHeader { id: 7, kind: 2 },
+            body: "hello".to_string(),
+        };
+        let inline = InlineMessage {
+            id: 7,
+            kind: 2,
+            body: "hello".to_string(),
+        };
+        assert_eq!(message.to_bytes(), inline.to_bytes());
+        assert_eq!(FlatMessage::from_bytes(&inline.to_bytes()), Some(message));
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian)]
+    struct EmptyUnit;
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian)]
+    struct EmptyBraced {}
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    #[binary(both_endian)]
+    struct EmptyTuple();
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Empties {
+        Unit,
+        Braced {},
+        Tuple(),
     }
 
     #[test]
-    fn test_vec_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: Vec<u8> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(v, <Vec<u8> as Binary>::from_bytes(&v.to_bytes()).unwrap());
+    fn test_empty_structs() {
+        assert!(EmptyUnit.to_bytes().is_empty());
+        assert!(EmptyBraced {}.to_bytes().is_empty());
+        assert!(EmptyTuple().to_bytes().is_empty());
+        assert_eq!(EmptyUnit::parse(&[1, 2]), Some((EmptyUnit, &[1, 2][..])));
+        assert_eq!(
+            EmptyBraced::parse(&[1, 2]),
+            Some((EmptyBraced {}, &[1, 2][..]))
+        );
+        assert_eq!(
+            EmptyTuple::parse(&[1, 2]),
+            Some((EmptyTuple(), &[1, 2][..]))
+        );
+        assert_eq!(EmptyUnit::from_bytes(&[]), Some(EmptyUnit));
+        assert_eq!(EmptyBraced::from_bytes(&[]), Some(EmptyBraced {}));
+        assert_eq!(EmptyTuple::from_bytes(&[]), Some(EmptyTuple()));
+        assert_eq!(EmptyUnit::parse_be(&[3]), Some((EmptyUnit, &[3][..])));
+        assert_eq!(
+            EmptyBraced::parse_be(&[3]),
+            Some((EmptyBraced {}, &[3][..]))
+        );
+        assert_eq!(EmptyTuple::parse_be(&[3]), Some((EmptyTuple(), &[3][..])));
+        assert_eq!(EmptyUnit::field_offsets(&[]), Some(vec![]));
+        assert_eq!(EmptyBraced::field_offsets(&[]), Some(vec![]));
+        assert_eq!(EmptyTuple::field_offsets(&[]), Some(vec![]));
+        assert_eq!(().to_bytes(), EmptyUnit.to_bytes());
+        assert_eq!(EmptyBraced::SCHEMA, "EmptyBraced");
+        assert_eq!(EmptyTuple::SCHEMA, "EmptyTuple");
+
+        for (tag, empty) in [Empties::Unit, Empties::Braced {}, Empties::Tuple()]
+            .into_iter()
+            .enumerate()
+        {
+            let bs = empty.to_bytes();
+            assert_eq!(bs, vec![tag as u8]);
+            assert_eq!(Empties::from_bytes(&bs), Some(empty));
         }
     }
 
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Either<L, R> {
+        Left(L),
+        Right(R),
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Never {}
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum Impossible {
+        Nope(std::convert::Infallible),
+    }
+
     #[test]
-    fn test_heap_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: BinaryHeap<u8> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(
-                v.iter().collect::<Vec<_>>(),
-                <BinaryHeap<u8> as Binary>::from_bytes(&v.to_bytes())
-                    .unwrap()
-                    .iter()
-                    .collect::<Vec<_>>()
-            );
-        }
+    fn test_infallible() {
+        use std::convert::Infallible;
+        assert!(Infallible::from_bytes(&[]).is_none());
+        assert!(Infallible::parse(&[0, 1, 2]).is_none());
+        let left: Either<u32, Infallible> = Either::Left(5);
+        assert_eq!(left, Either::from_bytes(&left.to_bytes()).unwrap());
+        assert!(Either::<u32, Infallible>::from_bytes(&[1]).is_none());
+        assert!(Never::from_bytes(&[0]).is_none());
+        assert!(Impossible::from_bytes(&[0]).is_none());
+    }
+
+    #[derive(derive::Binary, Debug, PartialEq)]
+    enum PrimitiveWhatsIt {
+        GoesEr(u128, u64),
+        Pozer { x: f32, y: f64, z: i32 },
+        Whaner,
+        Named(String),
     }
 
     #[test]
-    fn test_btreeset_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: BTreeSet<i32> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(
-                v,
-                <BTreeSet<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
+    fn test_truncated_enum() {
+        let goes_er = PrimitiveWhatsIt::GoesEr(u128::MAX, 9);
+        let bs = goes_er.to_bytes();
+        assert_eq!(bs.len(), 1 + 16 + 8);
+        assert_eq!(goes_er, PrimitiveWhatsIt::from_bytes(&bs).unwrap());
+        for len in 0..bs.len() {
+            assert!(PrimitiveWhatsIt::parse(&bs[..len]).is_none());
         }
+        let pozer = PrimitiveWhatsIt::Pozer {
+            x: 1.0,
+            y: 2.0,
+            z: 3,
+        };
+        let bs = pozer.to_bytes();
+        assert_eq!(pozer, PrimitiveWhatsIt::from_bytes(&bs).unwrap());
+        assert!(PrimitiveWhatsIt::parse(&bs[..bs.len() - 1]).is_none());
+        let whaner = PrimitiveWhatsIt::Whaner;
+        assert_eq!(
+            whaner,
+            PrimitiveWhatsIt::from_bytes(&whaner.to_bytes()).unwrap()
+        );
+        let named = PrimitiveWhatsIt::Named("name".to_string());
+        assert_eq!(
+            named,
+            PrimitiveWhatsIt::from_bytes(&named.to_bytes()).unwrap()
+        );
+        let truncated = WhatsIt::GoesEr(1, 2).to_bytes();
+        assert!(WhatsIt::parse(&truncated[..truncated.len() - 1]).is_none());
     }
 
     #[test]
-    fn test_hashset_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: HashSet<i32> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(
-                v,
-                <HashSet<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
-        }
+    fn test_parse_bytes() {
+        let bs = [1u8, 5, 3, 1, 2, 4, 5, 6];
+        assert!(parse_bytes::<9>(&bs).is_none());
+        assert!(parse_bytes::<8>(&bs).is_some());
+        assert!(parse_bytes::<7>(&bs).is_some());
+        assert!(parse_bytes::<6>(&bs).is_some());
+        assert!(parse_bytes::<5>(&bs).is_some());
+        assert!(parse_bytes::<4>(&bs).is_some());
+        assert!(parse_bytes::<3>(&bs).is_some());
+        assert!(parse_bytes::<2>(&bs).is_some());
+        assert!(parse_bytes::<1>(&bs).is_some());
+        assert!(parse_bytes::<0>(&bs).is_some());
     }
 
     #[test]
-    fn test_btreemap_binary() {
+    fn test_length_prefixed() {
+        let v: Vec<u32> = (0..1000).collect();
+        let bs = LengthPrefixed(&v[10..20]).to_bytes();
+        assert_eq!(bs, v[10..20].to_vec().to_bytes());
+        let LengthPrefixedOwned(parsed) = LengthPrefixedOwned::<u32>::from_bytes(&bs).unwrap();
+        assert_eq!(parsed, (10..20).collect::<Vec<u32>>());
+        assert_eq!(<Vec<u32> as Binary>::from_bytes(&bs).unwrap(), parsed);
+        let empty: &[String] = &[];
+        assert_eq!(
+            LengthPrefixed(empty).to_bytes(),
+            Vec::<String>::new().to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_delta_key_map() {
+        let m: BTreeMap<u64, u8> = (1000..1100).map(|k| (k, k as u8)).collect();
+        let delta = DeltaKeyMap(m.clone());
+        let bs = delta.to_bytes();
+        assert!(bs.len() < m.to_bytes().len() / 4);
+        assert_eq!(delta, DeltaKeyMap::from_bytes(&bs).unwrap());
+
         let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
+        for _i in 0..1000 {
             let length: usize = Standard.sample(&mut rng);
             let length = length % 100;
-            let keys: Vec<u64> = Standard.sample_iter(&mut rng).take(length).collect();
-            let v: BTreeMap<u64, u128> = keys
-                .iter()
-                .copied()
-                .zip(Standard.sample_iter(&mut rng))
-                .take(length)
+            let m: BTreeMap<u64, u16> = (0..length)
+                .map(|_| (Standard.sample(&mut rng), Standard.sample(&mut rng)))
                 .collect();
-            assert_eq!(
-                v,
-                <BTreeMap<u64, u128> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
+            let delta = DeltaKeyMap(m);
+            assert_eq!(delta, DeltaKeyMap::from_bytes(&delta.to_bytes()).unwrap());
         }
+        let extremes = DeltaKeyMap([(0, ()), (u64::MAX, ())].into_iter().collect());
+        assert_eq!(
+            extremes,
+            DeltaKeyMap::from_bytes(&extremes.to_bytes()).unwrap()
+        );
+
+        // a repeated key has a delta of zero
+        let mut repeated = Vec::new();
+        unparse_len(2, &mut repeated);
+        5u64.unparse(&mut repeated);
+        repeated.push(0);
+        assert!(DeltaKeyMap::<()>::from_bytes(&repeated).is_none());
+        // keys past u64::MAX
+        let mut overflowing = Vec::new();
+        unparse_len(2, &mut overflowing);
+        u64::MAX.unparse(&mut overflowing);
+        overflowing.push(1);
+        assert!(DeltaKeyMap::<()>::from_bytes(&overflowing).is_none());
+        // an overlong delta
+        let mut overlong = Vec::new();
+        unparse_len(2, &mut overlong);
+        5u64.unparse(&mut overlong);
+        overlong.extend_from_slice(&[0x81, 0x00]);
+        assert!(DeltaKeyMap::<()>::from_bytes(&overlong).is_none());
+        overlong.truncate(overlong.len() - 2);
+        overlong.push(1);
+        assert!(DeltaKeyMap::<()>::from_bytes(&overlong).is_some());
     }
 
     #[test]
-    fn test_hashmap_binary() {
+    fn test_check_length_stable() {
+        let example = Example {
+            a: 1,
+            b: -2,
+            c: 3.0,
+        };
+        let mut bs = example.to_bytes();
+        assert_eq!(example.encoded_len(), bs.len());
+        assert!(Example::check_length_stable(&bs));
+        assert_eq!(Example::parse_counted(&bs).unwrap().1, bs.len());
+        bs.push(0);
+        assert!(!Example::check_length_stable(&bs));
+        assert_eq!(Example::parse_counted(&bs).unwrap().1, bs.len() - 1);
+        assert!(!Example::check_length_stable(&bs[..10]));
+        let v = vec!["one".to_string(), "two".to_string()];
+        assert!(<Vec<String>>::check_length_stable(&v.to_bytes()));
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(0x1234abcdu32.to_hex(), "cdab3412");
+        assert_eq!(u32::from_hex("cdab3412"), Some(0x1234abcd));
+        assert_eq!(u32::from_hex("CDAB3412"), Some(0x1234abcd));
+        assert_eq!(().to_hex(), "");
+        assert_eq!(<()>::from_hex(""), Some(()));
+        let v = vec!["hex".to_string()];
+        assert_eq!(<Vec<String>>::from_hex(&v.to_hex()), Some(v));
+        assert!(u32::from_hex("cdab341").is_none());
+        assert!(u32::from_hex("cdab34").is_none());
+        assert!(u32::from_hex("cdab3412ff").is_none());
+        assert!(u32::from_hex("cdab34g2").is_none());
+        assert!(u32::from_hex("+dab3412").is_none());
+        assert!(u16::from_hex("é0").is_none());
+    }
+
+    #[test]
+    fn test_parse_counted() {
+        let mut bs = (7u32, 8u64).to_bytes();
+        bs.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(<(u32, u64)>::parse_counted(&bs), Some(((7, 8), 12)));
+        assert!(<(u32, u64)>::parse_counted(&bs[..11]).is_none());
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_parse_len() {
+        // the largest length fits in a usize, but the input doesn't hold that many elements
+        let bs = [0xFF; 8];
+        assert!(parse_len(&bs).is_some());
+        assert!(<Vec<u8>>::parse(&bs).is_none());
+    }
+
+    #[cfg(all(target_pointer_width = "32", not(feature = "len32")))]
+    #[test]
+    fn test_parse_len_overflow() {
+        let mut bs = (u32::MAX as u64 + 1).to_bytes();
+        assert!(parse_len(&bs).is_none());
+        bs.extend_from_slice(&[0; 100]);
+        assert!(<Vec<u8>>::parse(&bs).is_none());
+        assert!(String::parse(&bs).is_none());
+    }
+
+    #[test]
+    fn test_system_time() {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+        assert_eq!(UNIX_EPOCH.to_bytes(), (0i64, 0u32).to_bytes());
+        assert_eq!(
+            UNIX_EPOCH,
+            SystemTime::from_bytes(&UNIX_EPOCH.to_bytes()).unwrap()
+        );
+        let now = SystemTime::now();
+        assert_eq!(now, SystemTime::from_bytes(&now.to_bytes()).unwrap());
+        let after = UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        assert_eq!(
+            after.to_bytes(),
+            (1_700_000_000i64, 123_456_789u32).to_bytes()
+        );
+        assert_eq!(after, SystemTime::from_bytes(&after.to_bytes()).unwrap());
+        let before = UNIX_EPOCH - Duration::from_millis(1500);
+        assert_eq!(before.to_bytes(), (-2i64, 500_000_000u32).to_bytes());
+        assert_eq!(before, SystemTime::from_bytes(&before.to_bytes()).unwrap());
+        let whole_before = UNIX_EPOCH - Duration::from_secs(86400);
+        assert_eq!(whole_before.to_bytes(), (-86400i64, 0u32).to_bytes());
+        assert_eq!(
+            whole_before,
+            SystemTime::from_bytes(&whole_before.to_bytes()).unwrap()
+        );
+        assert!(SystemTime::from_bytes(&(0i64, 1_000_000_000u32).to_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_instant() {
+        use std::time::Duration;
+        let epoch = Instant::now();
+        let later = epoch + Duration::from_millis(5);
+        let mut bs = Vec::new();
+        unparse_instant(later, epoch, &mut bs);
+        assert_eq!(bs, (0i64, 5_000_000u32).to_bytes());
+        let (later_prime, rest) = parse_instant(&bs, epoch).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(later_prime.duration_since(epoch), Duration::from_millis(5));
+        // an instant before the epoch
+        let base = epoch + Duration::from_secs(10);
+        let mut bs = Vec::new();
+        unparse_instant(epoch + Duration::from_millis(8500), base, &mut bs);
+        assert_eq!(bs, (-2i64, 500_000_000u32).to_bytes());
+        let (earlier, _) = parse_instant(&bs, base).unwrap();
+        assert_eq!(base.duration_since(earlier), Duration::from_millis(1500));
+        assert!(parse_instant(&(0i64, 1_000_000_000u32).to_bytes(), epoch).is_none());
+    }
+
+    #[test]
+    fn test_shard() {
         let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let keys: Vec<u64> = Standard.sample_iter(&mut rng).take(length).collect();
-            let v: HashMap<u64, u128> = keys
+        let v: Vec<u64> = Standard.sample_iter(&mut rng).take(100_000).collect();
+        let bs = v.to_bytes();
+        let shards = shard::<u64>(&bs, 4).unwrap();
+        assert_eq!(shards.len(), 4);
+        assert!(shards
+            .iter()
+            .all(|&(count, s)| count == 25_000 && s.len() == 25_000 * 8));
+        assert_eq!(shard_fixed::<u64>(&bs, 4), Some(shards.clone()));
+        let parsed = std::thread::scope(|scope| {
+            let handles: Vec<_> = shards
                 .iter()
-                .copied()
-                .zip(Standard.sample_iter(&mut rng))
-                .take(length)
+                .map(|&(count, s)| scope.spawn(move || parse_shard::<u64>(count, s).unwrap()))
                 .collect();
-            assert_eq!(
-                v,
-                <HashMap<u64, u128> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
-        }
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(merge(parsed), v);
+
+        let strings: Vec<String> = (0..10).map(|i| "x".repeat(i)).collect();
+        let bs = strings.to_bytes();
+        let shards = shard::<String>(&bs, 3).unwrap();
+        let lengths: Vec<usize> = shards.iter().map(|&(count, _)| count).collect();
+        assert_eq!(lengths, vec![4, 3, 3]);
+        let parsed = shards
+            .iter()
+            .map(|&(count, s)| parse_shard(count, s).unwrap())
+            .collect();
+        assert_eq!(merge::<String>(parsed), strings);
+        // more shards than elements
+        let shards = shard::<String>(&bs, 20).unwrap();
+        assert_eq!(shards.len(), 10);
+        assert!(shards.iter().all(|&(count, s)| count == 1 && !s.is_empty()));
+        assert!(shard::<String>(&bs, 0).is_none());
+        assert!(shard::<String>(&bs[..bs.len() - 1], 2).is_none());
+        let mut trailing = bs.clone();
+        trailing.push(0);
+        assert!(shard::<String>(&trailing, 2).is_none());
+        assert!(shard_fixed::<u64>(&trailing, 2).is_none());
+        // a shard can't be parsed with leftover bytes
+        assert!(parse_shard::<String>(1, &[0; 9]).is_none());
+
+        // elements encoded as nothing survive sharding
+        let units = vec![(); 10].to_bytes();
+        let shards = shard::<()>(&units, 3).unwrap();
+        assert_eq!(shard_fixed::<()>(&units, 3), Some(shards.clone()));
+        let parsed = shards
+            .iter()
+            .map(|&(count, s)| parse_shard(count, s).unwrap())
+            .collect();
+        assert_eq!(merge::<()>(parsed), vec![(); 10]);
+        let mut too_many = Vec::new();
+        unparse_len(MAX_EMPTY_ELEMENTS + 1, &mut too_many);
+        assert!(shard::<()>(&too_many, 1).is_none());
+        assert!(shard_fixed::<()>(&too_many, 1).is_none());
     }
 
     #[test]
-    fn test_linkedlist_binary() {
+    fn test_ragged() {
         let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: LinkedList<i32> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(
-                v,
-                <LinkedList<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
+        for _i in 0..100 {
+            let rows: usize = Standard.sample(&mut rng);
+            let rows = rows % 100;
+            let nested: Vec<Vec<u32>> = (0..rows)
+                .map(|_| {
+                    let length: usize = Standard.sample(&mut rng);
+                    let length = length % 10;
+                    Standard.sample_iter(&mut rng).take(length).collect()
+                })
+                .collect();
+            let ragged = Ragged(nested.clone());
+            let bs = ragged.to_bytes();
+            assert_eq!(ragged, Ragged::from_bytes(&bs).unwrap());
+            // each inner vector costs four bytes for its offset, instead of its length prefix
+            let prefix = Vec::<u32>::new().to_bytes().len();
+            assert_eq!(nested.to_bytes().len() - bs.len(), (prefix - 4) * rows);
         }
+        let ragged = Ragged(vec![vec![1u8, 2], vec![], vec![3]]);
+        let bs = ragged.to_bytes();
+        assert_eq!(
+            &bs[bs.len() - 15..],
+            &[2, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 1, 2, 3]
+        );
+        assert_eq!(ragged.to_canonical_bytes(), bs);
+        let mut decreasing = bs.clone();
+        decreasing[bs.len() - 11] = 1;
+        assert!(Ragged::<u8>::from_bytes(&decreasing).is_none());
+        assert!(Ragged::<u8>::from_bytes(&bs[..bs.len() - 1]).is_none());
+        // an offset claiming billions of elements which aren't there
+        let mut hostile = Vec::new();
+        super::unparse_len(1, &mut hostile);
+        u32::MAX.unparse(&mut hostile);
+        assert!(Ragged::<u64>::from_bytes(&hostile).is_none());
+        assert!(Ragged::<u64>::from_bytes(&[&hostile[..], &[0; 8]].concat()).is_none());
     }
 
     #[test]
-    fn test_vecdeque_binary() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let length: usize = Standard.sample(&mut rng);
-            let length = length % 100;
-            let v: VecDeque<i32> = Standard.sample_iter(&mut rng).take(length).collect();
-            assert_eq!(
-                v,
-                <VecDeque<i32> as Binary>::from_bytes(&v.to_bytes()).unwrap()
-            );
+    fn test_footer() {
+        let records = vec![
+            (1u32, "first".to_string()),
+            (2, String::new()),
+            (3, "third".to_string()),
+        ];
+        let mut log = Vec::new();
+        for record in records.iter() {
+            log.extend_from_slice(&record.to_bytes_footer());
+        }
+        let mut rest = &log[..];
+        let mut reversed = Vec::new();
+        while !rest.is_empty() {
+            let (record, rest_prime) = <(u32, String)>::from_bytes_footer_reverse(rest).unwrap();
+            reversed.push(record);
+            rest = rest_prime;
         }
+        reversed.reverse();
+        assert_eq!(reversed, records);
+        assert!(u32::from_bytes_footer_reverse(&[1, 2, 3]).is_none());
+        // the footer claims more bytes than there are
+        assert!(u32::from_bytes_footer_reverse(&[1, 2, 3, 4, 0, 0, 0]).is_none());
+        // the record doesn't fill the bytes the footer claims
+        assert!(u8::from_bytes_footer_reverse(&[1, 2, 2, 0, 0, 0]).is_none());
     }
 
     #[test]
-    fn test_array() {
-        use std::mem::MaybeUninit;
-        let mut rng = thread_rng();
-        let samples = 1000;
-        for _i in 0..samples {
-            let array = {
-                let mut marray: [MaybeUninit<u64>; 1000] = [MaybeUninit::uninit(); 1000];
-                for i in 0..1000 {
-                    marray[i] = MaybeUninit::new(Standard.sample(&mut rng));
+    fn test_hash_encoding() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+        let value = (
+            vec!["hash".to_string(), "me".to_string()],
+            BTreeMap::from([(1u8, 2u64)]),
+        );
+        let mut encoding_hasher = DefaultHasher::new();
+        value.hash_encoding(&mut encoding_hasher);
+        // the default hasher doesn't depend on how its input is split between writes
+        let mut bytes_hasher = DefaultHasher::new();
+        bytes_hasher.write(&value.to_bytes());
+        assert_eq!(encoding_hasher.finish(), bytes_hasher.finish());
+        let mut other_hasher = DefaultHasher::new();
+        (vec!["hash".to_string()], BTreeMap::<u8, u64>::new()).hash_encoding(&mut other_hasher);
+        assert_ne!(other_hasher.finish(), bytes_hasher.finish());
+    }
+
+    #[test]
+    fn test_iter_fixed() {
+        let v: Vec<u64> = (0..1_000_000).collect();
+        let bs = v.to_bytes();
+        let sum: u64 = iter_fixed::<u64>(&bs).unwrap().sum();
+        assert_eq!(sum, 999_999 * 1_000_000 / 2);
+        assert_eq!(iter_fixed::<u64>(&bs).unwrap().count(), 1_000_000);
+        assert!(iter_fixed::<u64>(&bs[..bs.len() - 1]).is_none());
+        assert!(iter_fixed::<u32>(&bs).is_none());
+
+        let pairs = vec![(1u8, [2u16, 3]), (4, [5, 6])];
+        let bs = pairs.to_bytes();
+        assert_eq!(<(u8, [u16; 2])>::SIZE, 5);
+        assert_eq!(
+            iter_fixed::<(u8, [u16; 2])>(&bs)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            pairs
+        );
+        let bools = vec![true, false].to_bytes();
+        let mut invalid = bools.clone();
+        invalid[bools.len() - 1] = 2;
+        assert_eq!(iter_fixed::<bool>(&invalid).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_map_encoded() {
+        let v: Vec<u32> = (0..1000).collect();
+        let doubled = map_encoded(&v.to_bytes(), |x: u32| x as u64 * 2).unwrap();
+        assert_eq!(
+            Vec::<u64>::from_bytes(&doubled).unwrap(),
+            v.iter().map(|x| *x as u64 * 2).collect::<Vec<_>>()
+        );
+        let empty = map_encoded(&Vec::<u32>::new().to_bytes(), |x: u32| x).unwrap();
+        assert_eq!(empty, Vec::<u32>::new().to_bytes());
+        let bs = v.to_bytes();
+        assert!(map_encoded(&bs[..bs.len() - 1], |x: u32| x).is_none());
+        let mut invalid = vec![true, false].to_bytes();
+        *invalid.last_mut().unwrap() = 2;
+        assert!(map_encoded(&invalid, |b: bool| !b).is_none());
+    }
+
+    #[test]
+    fn test_byte_sinks() {
+        use std::io::{self, Write};
+        let value = (vec!["sink".to_string()], 7u64, -1i16);
+        let mut bs = vec![0xaa];
+        value.unparse(&mut bs);
+        assert_eq!(bs[0], 0xaa);
+        assert_eq!(&bs[1..], &value.to_bytes()[..]);
+
+        let mut sink = WriteSink::new(io::Cursor::new(Vec::new()));
+        value.unparse(&mut sink);
+        let example = Example {
+            a: 1,
+            b: -2,
+            c: 3.0,
+        };
+        example.unparse(&mut sink);
+        let written = sink.finish().unwrap().into_inner();
+        let (value_prime, rest) = <(Vec<String>, u64, i16)>::parse(&written).unwrap();
+        assert_eq!(value, value_prime);
+        assert_eq!(Example::from_bytes(rest), Some(example));
+
+        // a writer that fails part way through reports its first error
+        struct Failing(usize);
+        impl Write for Failing {
+            fn write(&mut self, bs: &[u8]) -> io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(io::Error::other("full"));
                 }
-                unsafe { MaybeUninit::array_assume_init(marray) }
-            };
-            assert_eq!(
-                array,
-                <[u64; 1000] as Binary>::from_bytes(&array.to_bytes()).unwrap()
-            );
+                let n = bs.len().min(self.0);
+                self.0 -= n;
+                Ok(n)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
         }
+        let mut sink = WriteSink::new(Failing(3));
+        value.unparse(&mut sink);
+        assert_eq!(sink.finish().err().unwrap().kind(), io::ErrorKind::Other);
     }
 
     #[test]
-    fn test_tuple() {
-        let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let t: (u128, char) = (Standard.sample(&mut rng), Standard.sample(&mut rng));
-            assert_eq!(
-                t,
-                <(u128, char) as Binary>::from_bytes(&t.to_bytes()).unwrap()
-            );
-            let x: i128 = Standard.sample(&mut rng);
-            let t = (t.0, t.1, x);
+    fn test_subsec_duration() {
+        use std::time::Duration;
+        assert_eq!(
+            SubsecDuration::try_from(Duration::from_millis(1500)),
+            Err(SubsecDurationError)
+        );
+        assert!(SubsecDuration::try_from(Duration::from_secs(1)).is_err());
+        let d = SubsecDuration::try_from(Duration::from_millis(500)).unwrap();
+        let bs = d.to_bytes();
+        assert_eq!(bs.len(), 4);
+        let d_prime = SubsecDuration::from_bytes(&bs).unwrap();
+        assert_eq!(d, d_prime);
+        assert_eq!(Duration::from(d_prime), Duration::from_millis(500));
+        let max = SubsecDuration::try_from(Duration::from_nanos(999_999_999)).unwrap();
+        assert_eq!(max, SubsecDuration::from_bytes(&max.to_bytes()).unwrap());
+        assert!(SubsecDuration::from_bytes(&1_000_000_000u32.to_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_percent_and_degrees() {
+        for percent in 0..=255u8 {
+            let p = Percent::new(percent);
+            assert_eq!(p.get(), percent.min(100));
+            assert_eq!(Percent::from_bytes(&p.to_bytes()), Some(p));
+            assert_eq!(Percent::from_bytes(&[percent]).is_some(), percent <= 100);
+        }
+        for tenths in [0, 1, 1800, 3599, 3600, 3601, u16::MAX] {
+            let d = Degrees::from_tenths(tenths);
+            assert_eq!(d.as_tenths(), tenths.min(3599));
+            assert_eq!(Degrees::from_bytes(&d.to_bytes()), Some(d));
             assert_eq!(
-                t,
-                <(u128, char, i128) as Binary>::from_bytes(&t.to_bytes()).unwrap()
+                Degrees::from_bytes(&tenths.to_bytes()).is_some(),
+                tenths < 3600
             );
         }
+        assert_eq!(Degrees::from_tenths(905).as_degrees(), 90.5);
     }
 
-    #[derive(derive::Binary, Debug, PartialEq)]
-    struct Example {
-        a: u128,
-        b: i64,
-        c: f32,
+    fn command_spec() -> CommandSpec {
+        CommandSpec {
+            program: "echo".to_string(),
+            args: vec!["hello".to_string(), "world".to_string()],
+            env: [("GREETING".to_string(), "hi".to_string())]
+                .into_iter()
+                .collect(),
+        }
     }
 
-    #[cfg(feature = "bls12_381")]
-    #[derive(derive::Binary, Debug, PartialEq)]
-    struct Nested {
-        m: BTreeMap<u64, Scalar>,
+    #[test]
+    fn test_command_spec_binary() {
+        let spec = command_spec();
+        assert_eq!(spec, CommandSpec::from_bytes(&spec.to_bytes()).unwrap());
+        let empty = CommandSpec::default();
+        assert_eq!(empty, CommandSpec::from_bytes(&empty.to_bytes()).unwrap());
     }
 
-    #[cfg(feature = "bls12_381")]
     #[test]
-    fn test_nested() {
-        use ff::Field;
-        let mut rng = thread_rng();
-        let samples = 100;
-        for _i in 0..samples {
-            let mut m = BTreeMap::new();
-            for _j in 0..100 {
-                let n: u64 = Standard.sample(&mut rng);
-                let n = n % 100;
-                m.insert(n, Scalar::random(&mut rng));
+    fn test_command_spec_to_command() {
+        use std::ffi::OsStr;
+        let command = std::process::Command::from(command_spec());
+        assert_eq!(command.get_program(), "echo");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec![OsStr::new("hello"), OsStr::new("world")]
+        );
+        assert_eq!(
+            command.get_envs().collect::<Vec<_>>(),
+            vec![(OsStr::new("GREETING"), Some(OsStr::new("hi")))]
+        );
+    }
+
+    #[test]
+    fn test_decoder_read_next() {
+        use std::io::{BufReader, Cursor, ErrorKind, Read};
+        let mut bs = Vec::new();
+        (1u32, "one".to_string()).unparse(&mut bs);
+        (2u32, "two".to_string()).unparse(&mut bs);
+        let mut r = Cursor::new(bs);
+        let mut decoder = Decoder::<(u32, String)>::new();
+        assert_eq!(decoder.read_next(&mut r).unwrap(), (1, "one".to_string()));
+        assert_eq!(decoder.read_next(&mut r).unwrap(), (2, "two".to_string()));
+        let e = decoder.read_next(&mut r).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+
+        // a small internal buffer forces the value to be assembled across many reads
+        let v: Vec<u64> = (0..100).collect();
+        let mut r = BufReader::with_capacity(3, Cursor::new(v.to_bytes()));
+        assert_eq!(
+            v,
+            Decoder::<Vec<u64>>::with_limit(808)
+                .read_next(&mut r)
+                .unwrap()
+        );
+
+        // the declared length is far more than we are willing to read
+        let mut r = Cursor::new(vec![0u8; 1000].to_bytes());
+        let e = Decoder::<Vec<u8>>::with_limit(100)
+            .read_next(&mut r)
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert_eq!(r.position(), 100);
+        let mut r = BufReader::with_capacity(7, Cursor::new(vec![0u8; 1000].to_bytes()));
+        let e = Decoder::<Vec<u8>>::with_limit(807)
+            .read_next(&mut r)
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // a large value arriving in full reads is parsed a logarithmic number of times
+        struct Counting<R>(R, usize);
+        impl<R: Read> Read for Counting<R> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.1 += 1;
+                self.0.read(buf)
             }
-            let n = Nested { m };
-            assert_eq!(n, Nested::from_bytes(&n.to_bytes()).unwrap());
         }
+        let v: Vec<u64> = (0..100_000).collect();
+        let mut r = Counting(Cursor::new(v.to_bytes()), 0);
+        assert_eq!(v, Decoder::<Vec<u64>>::new().read_next(&mut r).unwrap());
+        assert!(r.1 < 20);
     }
 
     #[test]
-    fn test_custom_named_struct() {
+    fn test_reframe_prefix() {
+        let v: Vec<u8> = (0..200).collect();
+        let framed = v.to_bytes();
+        let u16_framed = reframe_prefix(&framed, LEN_PREFIX, PrefixWidth::U16).unwrap();
+        assert_eq!(u16_framed.len(), 2 + 200);
+        assert_eq!(&u16_framed[0..2], &200u16.to_le_bytes());
+        assert_eq!(&u16_framed[2..], &v[..]);
+        let back = reframe_prefix(&u16_framed, PrefixWidth::U16, LEN_PREFIX).unwrap();
+        assert_eq!(back, framed);
+        assert_eq!(v, <Vec<u8>>::from_bytes(&back).unwrap());
+        let long: Vec<u8> = vec![0; 300];
+        assert!(reframe_prefix(&long.to_bytes(), LEN_PREFIX, PrefixWidth::U8).is_none());
+        assert!(reframe_prefix(&[1, 2, 3], PrefixWidth::U32, PrefixWidth::U64).is_none());
+    }
+
+    #[cfg(feature = "len32")]
+    #[test]
+    fn test_len32() {
         let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let example = Example {
-                a: Standard.sample(&mut rng),
-                b: Standard.sample(&mut rng),
-                c: Standard.sample(&mut rng),
-            };
-            assert_eq!(example, Example::from_bytes(&example.to_bytes()).unwrap());
+        for _i in 0..1000 {
+            let length: usize = Standard.sample(&mut rng);
+            let length = length % 100;
+            let v: Vec<u16> = Standard.sample_iter(&mut rng).take(length).collect();
+            let bs = v.to_bytes();
+            assert_eq!(&bs[0..4], &(length as u32).to_le_bytes());
+            assert_eq!(bs.len(), 4 + 2 * length);
+            assert_eq!(v, <Vec<u16>>::from_bytes(&bs).unwrap());
+            let s: String = Alphanumeric
+                .sample_iter(&mut rng)
+                .take(length)
+                .map(char::from)
+                .collect();
+            let m: BTreeMap<String, Vec<u16>> = [(s.clone(), v.clone())].into_iter().collect();
+            assert_eq!(s.to_bytes().len(), 4 + length);
+            assert_eq!(m, BTreeMap::from_bytes(&m.to_bytes()).unwrap());
         }
     }
 
-    #[derive(derive::Binary, Debug, PartialEq)]
-    struct Other(u128, i64);
-
     #[test]
-    fn test_custom_unnamed_struct() {
+    fn test_atomics() {
+        use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicU8, Ordering};
         let mut rng = thread_rng();
         let samples = 10000;
         for _i in 0..samples {
-            let other = Other(Standard.sample(&mut rng), Standard.sample(&mut rng));
-            assert_eq!(other, Other::from_bytes(&other.to_bytes()).unwrap());
+            let x: u64 = Standard.sample(&mut rng);
+            let a = AtomicU64::new(x);
+            assert_eq!(a.to_bytes(), x.to_bytes());
+            let a_prime = AtomicU64::from_bytes(&a.to_bytes()).unwrap();
+            assert_eq!(a.load(Ordering::SeqCst), a_prime.load(Ordering::SeqCst));
         }
+        let b = AtomicBool::new(true);
+        assert!(AtomicBool::from_bytes(&b.to_bytes())
+            .unwrap()
+            .load(Ordering::SeqCst));
+        assert!(AtomicBool::from_bytes(&[2]).is_none());
+        let i = AtomicI16::new(-300);
+        assert_eq!(
+            AtomicI16::from_bytes(&i.to_bytes())
+                .unwrap()
+                .load(Ordering::SeqCst),
+            -300
+        );
+        let u = AtomicU8::new(200);
+        u.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(
+            AtomicU8::from_bytes(&u.to_bytes()).unwrap().into_inner(),
+            201
+        );
     }
 
-    #[derive(derive::Binary, Debug, PartialEq)]
-    enum WhatsIt {
-        GoesEr(u128, u64),
-        Pozer { x: f32, y: f64, z: i32 },
-        Whaner,
+    #[test]
+    fn test_sync_reader() {
+        use super::{SyncReader, SYNC_MARKER};
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let first: (u64, String) = (Standard.sample(&mut rng), "first".to_string());
+            let second: (u64, String) = (Standard.sample(&mut rng), "second".to_string());
+            let mut garbage = || -> Vec<u8> {
+                let length: usize = Standard.sample(&mut rng);
+                Standard
+                    .sample_iter(&mut rng)
+                    .filter(|b: &u8| *b != SYNC_MARKER[0])
+                    .take(length % 50)
+                    .collect()
+            };
+            let mut bs = garbage();
+            bs.extend_from_slice(&first.to_bytes_synced());
+            bs.extend_from_slice(&garbage());
+            // a marker followed by nonsense must not derail the reader
+            bs.extend_from_slice(&SYNC_MARKER);
+            bs.extend_from_slice(&[0xff; 3]);
+            bs.extend_from_slice(&garbage());
+            bs.extend_from_slice(&second.to_bytes_synced());
+            bs.extend_from_slice(&garbage());
+            let values: Vec<(u64, String)> = SyncReader::new(&bs).collect();
+            assert_eq!(values, vec![first, second]);
+        }
     }
 
     #[test]
-    fn test_custom_enum() {
+    fn test_framed() {
         let mut rng = thread_rng();
-        let samples = 10000;
-        for _i in 0..samples {
-            let choice: u8 = Standard.sample(&mut rng);
-            let choice = choice % 3;
-            let whatsit = if choice == 0 {
-                WhatsIt::GoesEr(Standard.sample(&mut rng), Standard.sample(&mut rng))
-            } else if choice == 1 {
-                WhatsIt::Pozer {
-                    x: Standard.sample(&mut rng),
-                    y: Standard.sample(&mut rng),
-                    z: Standard.sample(&mut rng),
-                }
-            } else {
-                WhatsIt::Whaner
-            };
-            assert_eq!(whatsit, WhatsIt::from_bytes(&whatsit.to_bytes()).unwrap());
+        for _i in 0..1000 {
+            let x: (u64, String) = (Standard.sample(&mut rng), "first".to_string());
+            let y: Vec<i16> = Standard.sample_iter(&mut rng).take(10).collect();
+            let x_framed = x.to_framed_bytes();
+            assert_eq!(&x_framed[0..4], &(x.to_bytes().len() as u32).to_le_bytes());
+            assert_eq!(&x_framed[4..], &x.to_bytes()[..]);
+            let mut bs = x_framed;
+            bs.extend_from_slice(&y.to_framed_bytes());
+            let (x_prime, rest) = <(u64, String)>::parse_framed(&bs).unwrap();
+            assert_eq!(x, x_prime);
+            let (y_prime, rest) = <Vec<i16>>::parse_framed(rest).unwrap();
+            assert_eq!(y, y_prime);
+            assert!(rest.is_empty());
         }
+        // the frame holds more than the value
+        let mut bs = 5u32.to_framed_bytes();
+        bs[0] += 1;
+        bs.push(0);
+        assert!(u32::parse_framed(&bs).is_none());
+        // the frame holds less than the value
+        assert!(u64::parse_framed(&5u32.to_framed_bytes()).is_none());
+        // the frame is longer than the input
+        assert!(u32::parse_framed(&[8, 0, 0, 0, 1, 2, 3, 4]).is_none());
     }
 
     #[test]
-    fn test_parse_bytes() {
-        let bs = [1u8, 5, 3, 1, 2, 4, 5, 6];
-        assert!(parse_bytes::<9>(&bs).is_none());
-        assert!(parse_bytes::<8>(&bs).is_some());
-        assert!(parse_bytes::<7>(&bs).is_some());
-        assert!(parse_bytes::<6>(&bs).is_some());
-        assert!(parse_bytes::<5>(&bs).is_some());
-        assert!(parse_bytes::<4>(&bs).is_some());
-        assert!(parse_bytes::<3>(&bs).is_some());
-        assert!(parse_bytes::<2>(&bs).is_some());
-        assert!(parse_bytes::<1>(&bs).is_some());
-        assert!(parse_bytes::<0>(&bs).is_some());
+    fn test_records() {
+        use super::records;
+        use std::io::{Cursor, ErrorKind};
+        let values = vec![
+            (1u32, "one".to_string()),
+            (2, "two".to_string()),
+            (3, "three".to_string()),
+        ];
+        let mut bs = Vec::new();
+        for value in &values {
+            bs.extend_from_slice(&value.to_framed_bytes());
+        }
+        let read: Vec<(u32, String)> = records(Cursor::new(&bs))
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, values);
+
+        let truncated = &bs[..bs.len() - 2];
+        let mut it = records::<_, (u32, String)>(Cursor::new(truncated));
+        assert_eq!(it.next().unwrap().unwrap(), values[0]);
+        assert_eq!(it.next().unwrap().unwrap(), values[1]);
+        assert_eq!(
+            it.next().unwrap().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        assert!(it.next().is_none());
+
+        let mut it = records::<_, (u32, String)>(Cursor::new(&bs[..2]));
+        assert_eq!(
+            it.next().unwrap().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+        let mut it = records::<_, u8>(Cursor::new(&[2, 0, 0, 0, 1, 2]));
+        assert_eq!(
+            it.next().unwrap().unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        assert!(records::<_, u8>(Cursor::new(&[])).next().is_none());
+    }
+
+    #[test]
+    fn test_parse_schema() {
+        let record = (10u64, "ten".to_string(), true);
+        let mut bs = record.to_bytes();
+        bs.push(42);
+        let codes = [TypeCode::U64, TypeCode::String, TypeCode::Bool];
+        let (values, rest) = parse_schema(&codes, &bs).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                DynValue::U64(10),
+                DynValue::String("ten".to_string()),
+                DynValue::Bool(true)
+            ]
+        );
+        assert_eq!(rest, &[42]);
+        assert_eq!(
+            values.iter().map(DynValue::type_code).collect::<Vec<_>>(),
+            codes
+        );
+        let mut re_encoded = Vec::new();
+        for value in &values {
+            value.unparse(&mut re_encoded);
+        }
+        assert_eq!(re_encoded, record.to_bytes());
+        assert!(parse_schema(&codes, &bs[..bs.len() - 2]).is_none());
     }
 
     #[cfg(feature = "bls12_381")]
@@ -976,6 +7551,39 @@ mod test {
         }
     }
 
+    #[cfg(feature = "ed25519-dalek")]
+    #[test]
+    fn test_ed25519() {
+        use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+        let samples = 1000;
+        let mut rng = thread_rng();
+        for _i in 0..samples {
+            let seed: [u8; 32] = Standard.sample(&mut rng);
+            let signing_key = SigningKey::from_bytes(&seed);
+            let verifying_key = signing_key.verifying_key();
+            let message: [u8; 16] = Standard.sample(&mut rng);
+            let signature = signing_key.sign(&message);
+            let signing_key_bytes = Binary::to_bytes(&signing_key);
+            assert_eq!(signing_key_bytes, seed);
+            let signing_key_prime = <SigningKey as Binary>::from_bytes(&signing_key_bytes).unwrap();
+            assert_eq!(signing_key, signing_key_prime);
+            let verifying_key_prime =
+                <VerifyingKey as Binary>::from_bytes(&Binary::to_bytes(&verifying_key)).unwrap();
+            assert_eq!(verifying_key, verifying_key_prime);
+            let signature_bytes = Binary::to_bytes(&signature);
+            assert_eq!(signature_bytes.len(), 64);
+            let signature_prime = <Signature as Binary>::from_bytes(&signature_bytes).unwrap();
+            assert_eq!(signature, signature_prime);
+            assert!(verifying_key_prime
+                .verify(&message, &signature_prime)
+                .is_ok());
+        }
+        let mut not_a_point = [0u8; 32];
+        not_a_point[0] = 2;
+        assert!(<VerifyingKey as Binary>::from_bytes(&not_a_point).is_none());
+        assert!(<VerifyingKey as Binary>::from_bytes(&[0; 31]).is_none());
+    }
+
     #[cfg(feature = "blake3")]
     #[test]
     fn test_hash() {
@@ -992,4 +7600,1052 @@ mod test {
             );
         }
     }
+
+    #[cfg(feature = "json-value")]
+    #[test]
+    fn test_json_value() {
+        use serde_json::{json, Value};
+        let values = [
+            json!(null),
+            json!(true),
+            json!(false),
+            json!(0),
+            json!(u64::MAX),
+            json!(i64::MIN),
+            json!(-1),
+            json!(0.5),
+            json!(-1.25e300),
+            json!(""),
+            json!("hello"),
+            json!([]),
+            json!({}),
+            json!([1, -2, 3.5, "four", null, [true, [false]]]),
+            json!({
+                "name": "binary",
+                "tags": ["a", "b"],
+                "nested": { "x": 1, "y": -1, "z": 1.5, "w": { "deep": [null] } },
+            }),
+        ];
+        for v in values {
+            assert_eq!(v, Value::from_bytes(&v.to_bytes()).unwrap());
+        }
+
+        // nested objects count against the depth budget just as nested arrays do
+        let mut deep = json!(null);
+        for _i in 0..100 {
+            deep = json!({ "": deep });
+        }
+        let bs = deep.to_bytes();
+        assert!(Value::parse_depth_limited(&bs, 16).is_none());
+        assert_eq!(Value::parse_depth_limited(&bs, 128), Some((deep, &[][..])));
+
+        // a repeated key is rejected rather than overwriting the first
+        let mut bs = vec![5];
+        unparse_len(2, &mut bs);
+        for _i in 0..2 {
+            "a".to_string().unparse(&mut bs);
+            json!(null).unparse(&mut bs);
+        }
+        assert!(Value::from_bytes(&bs).is_none());
+    }
+
+    #[cfg(feature = "json-value")]
+    #[test]
+    fn test_json_number_kinds() {
+        use serde_json::{json, Value};
+        let u = json!(u64::MAX);
+        let i = json!(i64::MIN);
+        let f = json!(0.5);
+        let u_prime = Value::from_bytes(&u.to_bytes()).unwrap();
+        let i_prime = Value::from_bytes(&i.to_bytes()).unwrap();
+        let f_prime = Value::from_bytes(&f.to_bytes()).unwrap();
+        assert!(u_prime.is_u64());
+        assert!(i_prime.is_i64());
+        assert!(f_prime.is_f64());
+        assert!(Value::from_bytes(&[2, 3]).is_none());
+        assert!(Value::from_bytes(&[6]).is_none());
+    }
+
+    #[cfg(feature = "tinystr")]
+    #[test]
+    fn test_tinystr() {
+        use tinystr::TinyAsciiStr;
+        for tag in ["en", "fr", "und", "zh", "haw", "x", ""] {
+            let s = TinyAsciiStr::<4>::try_from_str(tag).unwrap();
+            let bs = s.to_bytes();
+            assert_eq!(bs.len(), 4);
+            assert_eq!(s, TinyAsciiStr::<4>::from_bytes(&bs).unwrap());
+        }
+        assert!(TinyAsciiStr::<4>::from_bytes(b"engl").is_some());
+        assert!(TinyAsciiStr::<4>::from_bytes(b"engli").is_none());
+        assert!(TinyAsciiStr::<4>::from_bytes(b"en").is_none());
+        assert!(TinyAsciiStr::<4>::from_bytes(&[b'e', 0xC3, 0xA9, 0]).is_none());
+        assert!(TinyAsciiStr::<4>::from_bytes(b"\0en\0").is_none());
+    }
+
+    #[cfg(feature = "half")]
+    #[test]
+    fn test_half() {
+        use half::{bf16, f16};
+        let values = [0.0f32, -0.0, 1.0, -1.5, 0.099975586, 65504.0, 6.1035156e-5];
+        for x in values {
+            let h = f16::from_f32(x);
+            let b = bf16::from_f32(x);
+            assert_eq!(h.to_bytes().len(), 2);
+            assert_eq!(b.to_bytes().len(), 2);
+            assert_eq!(
+                h.to_bits(),
+                f16::from_bytes(&h.to_bytes()).unwrap().to_bits()
+            );
+            assert_eq!(
+                b.to_bits(),
+                bf16::from_bytes(&b.to_bytes()).unwrap().to_bits()
+            );
+        }
+        // every bit pattern, including infinities, subnormals, and all NaN payloads, survives
+        for bits in 0..=u16::MAX {
+            let h = f16::from_bits(bits);
+            let b = bf16::from_bits(bits);
+            assert_eq!(bits, f16::from_bytes(&h.to_bytes()).unwrap().to_bits());
+            assert_eq!(bits, bf16::from_bytes(&b.to_bytes()).unwrap().to_bits());
+        }
+        assert!(f16::from_bytes(&f16::INFINITY.to_bytes())
+            .unwrap()
+            .is_infinite());
+        assert!(bf16::from_bytes(&bf16::NEG_INFINITY.to_bytes())
+            .unwrap()
+            .is_infinite());
+        assert!(f16::from_bytes(&f16::NAN.to_bytes()).unwrap().is_nan());
+        assert!(bf16::from_bytes(&bf16::NAN.to_bytes()).unwrap().is_nan());
+        // NaNs with other payloads are written canonically as the usual one
+        let payload = f16::from_bits(f16::NAN.to_bits() ^ 1);
+        assert!(payload.is_nan());
+        assert_eq!(payload.to_canonical_bytes(), f16::NAN.to_bytes());
+        let payload = bf16::from_bits(bf16::NAN.to_bits() ^ 1);
+        assert!(payload.is_nan());
+        assert_eq!(payload.to_canonical_bytes(), bf16::NAN.to_bytes());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_cas_entry() {
+        let value = (vec!["content".to_string()], 42u64);
+        let (hash, bs) = value.to_cas_entry();
+        assert_eq!(bs, value.to_bytes());
+        assert_eq!(hash, blake3::hash(&bs));
+        assert_eq!(<(Vec<String>, u64)>::verify_cas(&hash, &bs), Some(value));
+        let (other_hash, _) = 42u64.to_cas_entry();
+        assert!(<(Vec<String>, u64)>::verify_cas(&other_hash, &bs).is_none());
+        let mut tampered = bs.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(<(Vec<String>, u64)>::verify_cas(&hash, &tampered).is_none());
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_merkle_proof() {
+        use super::{merkle_proof, merkle_root, verify_merkle_proof};
+        let mut rng = thread_rng();
+        for len in 1..40 {
+            let items: Vec<u64> = Standard.sample_iter(&mut rng).take(len).collect();
+            let root = merkle_root(&items);
+            let middle = len / 2;
+            let proof = merkle_proof(&items, middle).unwrap();
+            assert!(verify_merkle_proof(
+                &root,
+                &items[middle],
+                middle,
+                len,
+                &proof
+            ));
+            assert!(!verify_merkle_proof(
+                &root,
+                &items[middle].wrapping_add(1),
+                middle,
+                len,
+                &proof
+            ));
+            for index in 0..len {
+                let proof = merkle_proof(&items, index).unwrap();
+                assert!(verify_merkle_proof(
+                    &root,
+                    &items[index],
+                    index,
+                    len,
+                    &proof
+                ));
+            }
+            assert!(merkle_proof(&items, len).is_none());
+        }
+        assert_eq!(merkle_root::<u64>(&[]), [0; 32]);
+    }
+
+    #[cfg(feature = "test-helpers")]
+    #[test]
+    fn test_assert_roundtrip() {
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let example = Example {
+                a: Standard.sample(&mut rng),
+                b: Standard.sample(&mut rng),
+                c: Standard.sample(&mut rng),
+            };
+            example.assert_roundtrip();
+        }
+    }
+
+    #[cfg(feature = "test-helpers")]
+    #[test]
+    #[should_panic(expected = "did not round trip: encoded as 0000c07f")]
+    fn test_assert_roundtrip_failure() {
+        f32::NAN.assert_roundtrip();
+    }
+
+    #[cfg(all(feature = "bytemuck", target_endian = "little"))]
+    #[test]
+    fn test_pod_array() {
+        use super::{parse_pod_array, unparse_pod_array};
+
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(C)]
+        struct Pixel {
+            x: u16,
+            y: u16,
+            rgba: u32,
+        }
+
+        // Pixel is repr(C) with no padding and every bit pattern is valid
+        unsafe impl bytemuck::Zeroable for Pixel {}
+        unsafe impl bytemuck::Pod for Pixel {}
+
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let pixels: [Pixel; 16] = std::array::from_fn(|_| Pixel {
+                x: Standard.sample(&mut rng),
+                y: Standard.sample(&mut rng),
+                rgba: Standard.sample(&mut rng),
+            });
+            let mut bs = Vec::new();
+            unparse_pod_array(&pixels, &mut bs);
+            let mut field_by_field = Vec::new();
+            for pixel in &pixels {
+                (pixel.x, pixel.y, pixel.rgba).unparse(&mut field_by_field);
+            }
+            assert_eq!(bs, field_by_field);
+            bs.push(7);
+            let (parsed, rest) = parse_pod_array::<Pixel, 16>(&bs).unwrap();
+            assert_eq!(pixels, parsed);
+            assert_eq!(rest, &[7]);
+            assert!(parse_pod_array::<Pixel, 16>(&bs[..bs.len() - 2]).is_none());
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{NaiveDate, NaiveTime};
+        let dates = [
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap(),
+            NaiveDate::from_ymd_opt(1, 1, 1).unwrap(),
+            NaiveDate::from_ymd_opt(-44, 3, 15).unwrap(),
+            NaiveDate::MIN,
+            NaiveDate::MAX,
+        ];
+        for date in dates {
+            assert_eq!(date, NaiveDate::from_bytes(&date.to_bytes()).unwrap());
+        }
+        assert!(NaiveDate::from_bytes(&i32::MAX.to_bytes()).is_none());
+        let times = [
+            NaiveTime::from_hms_nano_opt(23, 59, 59, 123_456_789).unwrap(),
+            NaiveTime::from_hms_nano_opt(0, 0, 0, 0).unwrap(),
+            NaiveTime::from_hms_nano_opt(23, 59, 59, 1_999_999_999).unwrap(),
+        ];
+        for time in times {
+            assert_eq!(time.to_bytes().len(), 8);
+            assert_eq!(time, NaiveTime::from_bytes(&time.to_bytes()).unwrap());
+        }
+        assert!(NaiveTime::from_bytes(&(86_400u32, 0u32).to_bytes()).is_none());
+        assert!(NaiveTime::from_bytes(&(0u32, 1_000_000_000u32).to_bytes()).is_none());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time() {
+        use time::{Date, Duration, Month, OffsetDateTime, UtcOffset};
+        let datetimes = [
+            OffsetDateTime::UNIX_EPOCH,
+            OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_456_789).unwrap(),
+            OffsetDateTime::from_unix_timestamp_nanos(-1).unwrap(),
+            OffsetDateTime::from_unix_timestamp(-2_208_988_800).unwrap(),
+            Date::MIN.midnight().assume_utc(),
+            Date::MAX.midnight().assume_utc(),
+        ];
+        for datetime in datetimes {
+            assert_eq!(datetime.to_bytes().len(), 16);
+            assert_eq!(
+                OffsetDateTime::from_bytes(&datetime.to_bytes()),
+                Some(datetime)
+            );
+        }
+        let offset = OffsetDateTime::UNIX_EPOCH.to_offset(UtcOffset::from_hms(-5, 0, 0).unwrap());
+        let parsed = OffsetDateTime::from_bytes(&offset.to_bytes()).unwrap();
+        assert_eq!(parsed, offset);
+        assert_eq!(parsed.offset(), UtcOffset::UTC);
+        assert!(OffsetDateTime::from_bytes(&i128::MAX.to_bytes()).is_none());
+        for date in [
+            Date::from_calendar_date(2024, Month::February, 29).unwrap(),
+            Date::from_calendar_date(1969, Month::December, 31).unwrap(),
+            Date::MIN,
+            Date::MAX,
+        ] {
+            assert_eq!(Date::from_bytes(&date.to_bytes()), Some(date));
+        }
+        assert!(Date::from_bytes(&i32::MAX.to_bytes()).is_none());
+        for duration in [
+            Duration::ZERO,
+            Duration::new(-3, -500),
+            Duration::new(7, 999_999_999),
+            Duration::MIN,
+            Duration::MAX,
+        ] {
+            assert_eq!(Duration::from_bytes(&duration.to_bytes()), Some(duration));
+        }
+        assert!(Duration::from_bytes(&i128::MAX.to_bytes()).is_none());
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn test_glam_vec3() {
+        use glam::Vec3;
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let v = Vec3::new(
+                Standard.sample(&mut rng),
+                Standard.sample(&mut rng),
+                Standard.sample(&mut rng),
+            );
+            let bs = v.to_bytes();
+            assert_eq!(bs.len(), 12);
+            let v_prime = Vec3::from_bytes(&bs).unwrap();
+            assert_eq!(v.x.to_bits(), v_prime.x.to_bits());
+            assert_eq!(v.y.to_bits(), v_prime.y.to_bits());
+            assert_eq!(v.z.to_bits(), v_prime.z.to_bits());
+        }
+        let nan = Vec3::new(f32::NAN, -0.0, f32::INFINITY);
+        let nan_prime = Vec3::from_bytes(&nan.to_bytes()).unwrap();
+        assert_eq!(
+            nan.to_array().map(f32::to_bits),
+            nan_prime.to_array().map(f32::to_bits)
+        );
+        let payload = Vec3::new(-f32::NAN, -0.0, f32::INFINITY);
+        assert_ne!(payload.to_bytes(), nan.to_bytes());
+        assert_eq!(payload.to_canonical_bytes(), nan.to_bytes());
+        let q = glam::Quat::from_xyzw(0.0, 0.6, 0.0, 0.8);
+        assert_eq!(q, glam::Quat::from_bytes(&q.to_bytes()).unwrap());
+        assert_eq!(q.to_bytes(), [0.0f32, 0.6, 0.0, 0.8].to_bytes());
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn test_glam_mat4() {
+        use glam::Mat4;
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let mut components = [0f32; 16];
+            for c in components.iter_mut() {
+                *c = Standard.sample(&mut rng);
+            }
+            let m = Mat4::from_cols_array(&components);
+            let bs = m.to_bytes();
+            assert_eq!(bs, components.to_bytes());
+            let m_prime = Mat4::from_bytes(&bs).unwrap();
+            assert_eq!(
+                m.to_cols_array().map(f32::to_bits),
+                m_prime.to_cols_array().map(f32::to_bits)
+            );
+        }
+        // columns come first
+        let m = Mat4::from_cols(
+            glam::Vec4::new(1.0, 2.0, 3.0, 4.0),
+            glam::Vec4::ZERO,
+            glam::Vec4::ZERO,
+            glam::Vec4::ZERO,
+        );
+        assert_eq!(
+            &m.to_bytes()[0..16],
+            &[1.0f32, 2.0, 3.0, 4.0].to_bytes()[..]
+        );
+    }
+
+    #[cfg(feature = "uom")]
+    #[test]
+    fn test_uom() {
+        use uom::si::f64::{Length, Velocity};
+        use uom::si::length::{kilometer, meter};
+        use uom::si::velocity::{kilometer_per_hour, meter_per_second};
+        let mut rng = thread_rng();
+        for _i in 0..1000 {
+            let l = Length::new::<meter>(Standard.sample(&mut rng));
+            let bs = l.to_bytes();
+            assert_eq!(bs, l.value.to_bytes());
+            assert_eq!(
+                l.value.to_bits(),
+                Length::from_bytes(&bs).unwrap().value.to_bits()
+            );
+        }
+        let l = Length::new::<kilometer>(1.5);
+        assert_eq!(l.to_bytes(), 1500.0f64.to_bytes());
+        let l_prime = Length::from_bytes(&l.to_bytes()).unwrap();
+        assert_eq!(l_prime.get::<meter>(), 1500.0);
+
+        let v = Velocity::new::<kilometer_per_hour>(36.0);
+        let v_prime = Velocity::from_bytes(&v.to_bytes()).unwrap();
+        assert_eq!(v.value.to_bits(), v_prime.value.to_bits());
+        assert_eq!(v_prime.get::<meter_per_second>(), 10.0);
+        let nan = Length::new::<meter>(-f64::NAN);
+        assert_eq!(nan.to_canonical_bytes(), f64::NAN.to_bytes());
+    }
+
+    #[cfg(feature = "rust_decimal")]
+    #[test]
+    fn test_rust_decimal() {
+        use rust_decimal::Decimal;
+        use std::str::FromStr;
+        let decimals = [
+            Decimal::ZERO,
+            Decimal::from_str("-0").unwrap(),
+            Decimal::from_str("-0.000").unwrap(),
+            Decimal::from_str("1.50").unwrap(),
+            Decimal::from_str("-1234567.89").unwrap(),
+            Decimal::from_str("0.0000000000000000000000000001").unwrap(),
+            Decimal::MAX,
+            Decimal::MIN,
+            Decimal::new(-1, 28),
+        ];
+        for d in decimals {
+            let bs = d.to_bytes();
+            assert_eq!(bs.len(), 16);
+            let d_prime = Decimal::from_bytes(&bs).unwrap();
+            assert_eq!(d, d_prime);
+            assert_eq!(d.scale(), d_prime.scale());
+            assert_eq!(d.is_sign_negative(), d_prime.is_sign_negative());
+            assert_eq!(d.to_string(), d_prime.to_string());
+        }
+        assert_eq!(Decimal::new(-1, 28).to_bytes()[2], 28);
+        assert!(Decimal::from_bytes(&[0; 15]).is_none());
+        // flags outside the sign and scale, and scales past 28, are rejected rather than masked
+        for (i, flag) in [(0, 1), (1, 0x80), (3, 0x40), (2, 29), (2, 0x80)] {
+            let mut bs = Decimal::ONE.to_bytes();
+            bs[i] |= flag;
+            assert!(Decimal::from_bytes(&bs).is_none());
+        }
+        let mut bs = Decimal::ONE.to_bytes();
+        bs[2] = 28;
+        assert_eq!(Decimal::from_bytes(&bs), Some(Decimal::new(1, 28)));
+    }
+
+    #[cfg(feature = "roaring")]
+    #[test]
+    fn test_roaring() {
+        use roaring::RoaringBitmap;
+        let mut bitmap = RoaringBitmap::new();
+        bitmap.insert_range(1_000_000..4_000_000);
+        bitmap.insert(u32::MAX);
+        for i in 0..1000 {
+            bitmap.insert(i * 7);
+        }
+        let bs = bitmap.to_bytes();
+        assert_eq!(bitmap, RoaringBitmap::from_bytes(&bs).unwrap());
+        // dense containers use a bit per element, rather than the four bytes of a u32
+        assert!(bs.len() < bitmap.len() as usize / 4);
+        let empty = RoaringBitmap::new();
+        assert_eq!(empty, RoaringBitmap::from_bytes(&empty.to_bytes()).unwrap());
+        assert!(RoaringBitmap::from_bytes(&bs[..bs.len() - 1]).is_none());
+        let mut pair = bs.clone();
+        empty.unparse(&mut pair);
+        let (first, rest) = RoaringBitmap::parse(&pair).unwrap();
+        assert_eq!(first, bitmap);
+        assert_eq!(RoaringBitmap::from_bytes(rest).unwrap(), empty);
+    }
+
+    #[cfg(feature = "k256")]
+    #[test]
+    fn test_k256() {
+        use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+        use k256::{PublicKey, SecretKey};
+        let mut rng = thread_rng();
+        let samples = 100;
+        for _i in 0..samples {
+            let public_key = SecretKey::random(&mut rng).public_key();
+            let bs = public_key.to_bytes();
+            assert_eq!(bs.len(), 33);
+            assert_eq!(public_key, PublicKey::from_bytes(&bs).unwrap());
+
+            let signing_key = SigningKey::random(&mut rng);
+            let message: [u8; 32] = Standard.sample(&mut rng);
+            let signature: Signature = signing_key.sign(&message);
+            let bs = Binary::to_bytes(&signature);
+            assert_eq!(bs.len(), 64);
+            assert_eq!(signature, <Signature as Binary>::from_bytes(&bs).unwrap());
+        }
+        // not a point on the curve, or not compressed
+        let mut invalid = [0u8; 33];
+        invalid[0] = 2;
+        assert!(PublicKey::from_bytes(&invalid).is_none());
+        invalid[0] = 4;
+        assert!(PublicKey::from_bytes(&invalid).is_none());
+        // r and s can't be zero
+        assert!(<Signature as Binary>::from_bytes(&[0; 64]).is_none());
+        // the high s counterpart of a valid signature is malleable
+        let signing_key = SigningKey::random(&mut rng);
+        let signature: Signature = signing_key.sign(b"message");
+        let (r, s) = signature.split_scalars();
+        let high = Signature::from_scalars(r, -*s).unwrap();
+        assert!(<Signature as Binary>::from_bytes(&high.to_bytes()).is_none());
+    }
+
+    #[cfg(feature = "generic-array")]
+    #[test]
+    fn test_generic_array() {
+        use generic_array::typenum::{U3, U32};
+        use generic_array::GenericArray;
+        let digest: GenericArray<u8, U32> = GenericArray::clone_from_slice(&[7; 32]);
+        let bs = digest.to_bytes();
+        assert_eq!(bs, [7u8; 32].to_bytes());
+        assert_eq!(<GenericArray<u8, U32>>::from_bytes(&bs), Some(digest));
+        assert!(<GenericArray<u8, U32>>::from_bytes(&bs[..31]).is_none());
+        assert_eq!(<GenericArray<u8, U32> as FixedSize>::SIZE, 32);
+        let words: GenericArray<u16, U3> = GenericArray::clone_from_slice(&[1, 2, 3]);
+        assert_eq!(
+            <GenericArray<u16, U3>>::from_bytes(&words.to_bytes()),
+            Some(words)
+        );
+    }
+
+    #[cfg(feature = "arrayvec")]
+    #[test]
+    fn test_arrayvec() {
+        use arrayvec::{ArrayString, ArrayVec};
+        for length in [0, 16, 32] {
+            let v: ArrayVec<u8, 32> = (0..length).collect();
+            let bs = v.to_bytes();
+            assert_eq!(bs, v.to_vec().to_bytes());
+            assert_eq!(v, ArrayVec::<u8, 32>::from_bytes(&bs).unwrap());
+        }
+        let too_long: Vec<u8> = (0..33).collect();
+        assert!(ArrayVec::<u8, 32>::from_bytes(&too_long.to_bytes()).is_none());
+        let huge_length = u64::MAX.to_bytes();
+        assert!(ArrayVec::<u8, 32>::parse(&huge_length).is_none());
+
+        for length in [0, 4, 8] {
+            let s = ArrayString::<8>::from(&"abcdefgh"[..length]).unwrap();
+            let bs = s.to_bytes();
+            assert_eq!(bs, s.to_string().to_bytes());
+            assert_eq!(s, ArrayString::<8>::from_bytes(&bs).unwrap());
+        }
+        assert!(ArrayString::<8>::from_bytes(&"abcdefghi".to_string().to_bytes()).is_none());
+        assert!(ArrayString::<8>::from_bytes(&vec![0xFFu8].to_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_decoder() {
+        let v: Vec<u64> = (0..10).collect();
+        let bs = v.to_bytes();
+        let mut decoder = Decoder::<Vec<u64>>::new();
+        let mut decoded = Vec::new();
+        for b in &bs {
+            decoder.push_bytes(&[*b]);
+            if let Some(x) = decoder.try_next().unwrap() {
+                decoded.push(x);
+            }
+        }
+        assert_eq!(decoded, vec![v]);
+        assert!(decoder.buffered().is_empty());
+
+        let mut decoder = Decoder::<u32>::default();
+        decoder.push_bytes(&[1, 0, 0, 0, 2, 0]);
+        decoder.push_bytes(&[0, 0, 3]);
+        assert_eq!(decoder.try_next(), Ok(Some(1)));
+        assert_eq!(decoder.try_next(), Ok(Some(2)));
+        assert_eq!(decoder.try_next(), Ok(None));
+        assert_eq!(decoder.try_next(), Ok(None));
+        assert_eq!(decoder.buffered(), &[3]);
+        decoder.push_bytes(&[0, 0, 0]);
+        assert_eq!(decoder.try_next(), Ok(Some(3)));
+
+        // many values arriving at once are each decoded without shifting the rest
+        let values: Vec<u16> = (0..10_000).collect();
+        let mut decoder = Decoder::<u16>::new();
+        for chunk in values.chunks(1000) {
+            decoder.push_bytes(&chunk.to_vec().to_bytes()[LEN_PREFIX.bytes()..]);
+            // the input already decoded is dropped before it takes up most of the buffer
+            assert!(decoder.buffer.len() <= 2 * 2000);
+            let mut decoded = Vec::new();
+            while let Some(x) = decoder.try_next().unwrap() {
+                decoded.push(x);
+            }
+            assert_eq!(decoded, chunk);
+        }
+        assert!(decoder.buffered().is_empty());
+
+        // a length prefix which claims more than the limit is never satisfied
+        let mut decoder = Decoder::<Vec<u8>>::with_limit(16);
+        let mut huge = Vec::new();
+        unparse_len(1 << 20, &mut huge);
+        decoder.push_bytes(&huge);
+        assert_eq!(decoder.try_next(), Ok(None));
+        decoder.push_bytes(&[0; 16]);
+        assert_eq!(decoder.try_next(), Err(BinaryError::Invalid));
+        assert_eq!(decoder.try_next(), Err(BinaryError::Invalid));
+        let mut decoder = Decoder::<Vec<u8>>::with_limit(16);
+        decoder.push_bytes(&vec![7u8; 8].to_bytes());
+        assert_eq!(decoder.try_next(), Ok(Some(vec![7; 8])));
+    }
+
+    #[test]
+    fn test_checksummed() {
+        assert_eq!(super::crc32(b"123456789"), 0xCBF4_3926);
+        let value = Checksummed(("payload".to_string(), 42u64));
+        let bs = value.to_bytes();
+        assert_eq!(bs.len(), value.0.to_bytes().len() + 4);
+        assert_eq!(Checksummed::from_bytes(&bs), Some(value.clone()));
+        for i in 0..bs.len() {
+            let mut corrupted = bs.clone();
+            corrupted[i] ^= 0x10;
+            assert!(<Checksummed<(String, u64)>>::from_bytes(&corrupted).is_none());
+        }
+        let mut trailing = bs.clone();
+        trailing.push(9);
+        assert_eq!(Checksummed::parse(&trailing), Some((value, &[9][..])));
+    }
+
+    #[test]
+    fn test_compact_char() {
+        for (c, width) in [('a', 1), ('é', 2), ('€', 3), ('🦀', 4)] {
+            let bs = CompactChar(c).to_bytes();
+            assert_eq!(bs, c.to_string().into_bytes());
+            assert_eq!(bs.len(), width);
+            assert_eq!(CompactChar::from_bytes(&bs), Some(CompactChar(c)));
+        }
+        assert_eq!(
+            CompactChar::parse(b"xy"),
+            Some((CompactChar('x'), &b"y"[..]))
+        );
+        // A lone continuation byte, an overlong encoding, a surrogate, and a truncated sequence.
+        assert_eq!(CompactChar::parse(&[0x80]), None);
+        assert_eq!(CompactChar::parse(&[0xC0, 0x80]), None);
+        assert_eq!(CompactChar::parse(&[0xED, 0xA0, 0x80]), None);
+        assert_eq!(CompactChar::parse(&[0xF0, 0x9F, 0xA6]), None);
+        assert_eq!(CompactChar::parse(&[]), None);
+    }
+
+    #[test]
+    fn test_locks() {
+        let m = Mutex::new(vec![1u8, 2, 3]);
+        let bs = m.to_bytes();
+        assert_eq!(bs, vec![1u8, 2, 3].to_bytes());
+        let parsed = Mutex::<Vec<u8>>::from_bytes(&bs).unwrap();
+        assert_eq!(*parsed.lock().unwrap(), vec![1, 2, 3]);
+        let _ = std::panic::catch_unwind(|| {
+            let _guard = m.lock().unwrap();
+            panic!("poison");
+        });
+        assert!(m.is_poisoned());
+        assert_eq!(m.to_bytes(), bs);
+        let l = RwLock::new(vec![4u8, 5]);
+        let parsed = RwLock::<Vec<u8>>::from_bytes(&l.to_bytes()).unwrap();
+        assert_eq!(*parsed.read().unwrap(), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_varints() {
+        let boundaries = [
+            (0, 1),
+            (127, 1),
+            (128, 2),
+            ((1 << 14) - 1, 2),
+            (1 << 14, 3),
+            ((1 << 21) - 1, 3),
+            (1 << 21, 4),
+            ((1 << 56) - 1, 8),
+            (1 << 56, 9),
+            ((1 << 63) - 1, 9),
+            (1 << 63, 10),
+            (u64::MAX, 10),
+        ];
+        for (n, width) in boundaries {
+            let bs = VarU64(n).to_bytes();
+            assert_eq!(bs.len(), width);
+            assert_eq!(VarU64::from_bytes(&bs), Some(VarU64(n)));
+        }
+        for (n, width) in [(0, 1), (-1, 1), (63, 1), (-64, 1), (64, 2), (-65, 2)] {
+            let bs = VarI64(n).to_bytes();
+            assert_eq!(bs.len(), width);
+            assert_eq!(VarI64::from_bytes(&bs), Some(VarI64(n)));
+        }
+        for n in [i64::MIN, i64::MAX] {
+            assert_eq!(VarI64(n).to_bytes().len(), 10);
+            assert_eq!(VarI64::from_bytes(&VarI64(n).to_bytes()), Some(VarI64(n)));
+        }
+        // 1, padded out to ten bytes with redundant zero groups
+        let mut overlong = vec![0x81];
+        overlong.extend_from_slice(&[0x80; 8]);
+        overlong.push(0x00);
+        assert_eq!(VarU64::parse(&overlong), None);
+        assert_eq!(VarU64::parse(&[0x80, 0x00]), None);
+        // more than 64 bits, and a final byte that never comes
+        let mut too_wide = vec![0xFF; 9];
+        too_wide.push(0x02);
+        assert_eq!(VarU64::parse(&too_wide), None);
+        assert_eq!(VarU64::parse(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_delta_vec() {
+        for n in [0, 1, -1, 63, -64, 64, i64::MIN, i64::MAX] {
+            assert_eq!(super::unzigzag(super::zigzag(n)), n);
+        }
+        assert_eq!(super::zigzag(-1), 1);
+        assert_eq!(super::zigzag(1), 2);
+        let sorted: Vec<u64> = (0..1000).map(|i| 1_700_000_000 + i * 15).collect();
+        let delta = DeltaVec(sorted.clone());
+        let bs = delta.to_bytes();
+        assert_eq!(DeltaVec::from_bytes(&bs), Some(delta));
+        assert!(bs.len() < sorted.to_bytes().len() / 7);
+        let unsorted = DeltaVec(vec![10, 3, u64::MAX, 0, 7, 7, u64::MAX / 2]);
+        assert_eq!(DeltaVec::from_bytes(&unsorted.to_bytes()), Some(unsorted));
+        let empty = DeltaVec(vec![]);
+        assert_eq!(empty.to_bytes(), Vec::<u64>::new().to_bytes());
+        assert_eq!(DeltaVec::from_bytes(&empty.to_bytes()), Some(empty));
+        let mut rng = thread_rng();
+        for _i in 0..100 {
+            let values: Vec<u64> = Standard.sample_iter(&mut rng).take(20).collect();
+            let v = DeltaVec(values);
+            assert_eq!(DeltaVec::from_bytes(&v.to_bytes()), Some(v));
+        }
+    }
+
+    #[test]
+    fn test_packed_bits() {
+        let prefix = LEN_PREFIX.bytes();
+        for n in [0usize, 7, 8, 9] {
+            let bits = PackedBits((0..n).map(|i| i % 3 == 0).collect());
+            let bs = bits.to_bytes();
+            assert_eq!(bs.len(), prefix + n.div_ceil(8));
+            assert_eq!(PackedBits::from_bytes(&bs), Some(bits.clone()));
+            assert_eq!(PackedBits::parse_strict(&bs), Some((bits, &[][..])));
+        }
+        let bits = PackedBits(vec![true; 9]);
+        assert_eq!(bits.to_bytes()[prefix..], [0xff, 0x01]);
+        let mut padded = bits.to_bytes();
+        padded[prefix + 1] |= 0x80;
+        assert_eq!(PackedBits::from_bytes(&padded), Some(bits));
+        assert!(PackedBits::parse_strict(&padded).is_none());
+        assert!(PackedBits::from_bytes(&padded[..prefix + 1]).is_none());
+    }
+
+    #[cfg(feature = "fixedbitset")]
+    #[test]
+    fn test_fixedbitset() {
+        use fixedbitset::FixedBitSet;
+        let prefix = LEN_PREFIX.bytes();
+        let mut rng = thread_rng();
+        for len in [0, 1, 7, 8, 9, 63, 64, 65, 1000] {
+            let mut set = FixedBitSet::with_capacity(len);
+            for i in 0..len {
+                let b: bool = Standard.sample(&mut rng);
+                set.set(i, b);
+            }
+            let bs = set.to_bytes();
+            assert_eq!(bs.len(), prefix + len.div_ceil(8));
+            let set_prime = FixedBitSet::from_bytes(&bs).unwrap();
+            assert_eq!(set_prime.len(), len);
+            assert_eq!(set, set_prime);
+        }
+        let mut full = FixedBitSet::with_capacity(10);
+        full.insert_range(..);
+        let bs = full.to_bytes();
+        assert_eq!(&bs[prefix..], &[0xFF, 0x03]);
+        // a bit past the length
+        let mut leaking = bs.clone();
+        leaking[prefix + 1] |= 0x04;
+        assert!(FixedBitSet::from_bytes(&leaking).is_none());
+        assert!(FixedBitSet::from_bytes(&bs[..prefix + 1]).is_none());
+    }
+
+    #[test]
+    fn test_for_vec() {
+        let mut rng = thread_rng();
+        let clustered: Vec<u64> = Standard
+            .sample_iter(&mut rng)
+            .take(1000)
+            .map(|offset: u64| 1_000_000 + offset % 60_000)
+            .collect();
+        let v = ForVec(clustered.clone());
+        let bs = v.to_bytes();
+        assert_eq!(ForVec::from_bytes(&bs), Some(v));
+        assert!(bs.len() * 2 < clustered.to_bytes().len());
+        for values in [vec![], vec![7u32], vec![5, 5, 5], vec![0, u32::MAX, 1]] {
+            let v = ForVec(values);
+            assert_eq!(ForVec::from_bytes(&v.to_bytes()), Some(v));
+        }
+        let full = ForVec(vec![0, u64::MAX]);
+        assert_eq!(ForVec::from_bytes(&full.to_bytes()), Some(full));
+        // a value which doesn't fit in the element type
+        assert!(ForVec::<u8>::from_bytes(&ForVec(vec![200u64, 300]).to_bytes()).is_none());
+        // a base which isn't the least value
+        let mut low_base = ForVec(vec![10u64, 11]).to_bytes();
+        let first_offset = low_base.len() - 2;
+        low_base[first_offset] = 1;
+        assert!(ForVec::<u64>::from_bytes(&low_base).is_none());
+        // a wider width than needed
+        let mut wide = Vec::new();
+        super::unparse_len(2, &mut wide);
+        10u64.unparse(&mut wide);
+        wide.extend_from_slice(&[2, 0, 0, 1, 0]);
+        assert!(ForVec::<u64>::from_bytes(&wide).is_none());
+        // a huge length of values equal to the base, which take no bytes each
+        let mut equal = Vec::new();
+        super::unparse_len(u32::MAX as usize, &mut equal);
+        10u64.unparse(&mut equal);
+        equal.push(0);
+        assert!(ForVec::<u64>::from_bytes(&equal).is_none());
+        let many = ForVec(vec![3u64; MAX_EMPTY_ELEMENTS]);
+        assert_eq!(ForVec::from_bytes(&many.to_bytes()), Some(many));
+        let too_many = ForVec(vec![3u64; MAX_EMPTY_ELEMENTS + 1]);
+        assert!(ForVec::<u64>::from_bytes(&too_many.to_bytes()).is_none());
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_ndarray() {
+        use ndarray::{Array, Array1, Array2, IxDyn};
+        let a = Array2::from_shape_fn((3, 4), |(i, j)| i as f64 * 10.0 + j as f64);
+        let bs = a.to_bytes();
+        let mut shape = Vec::new();
+        unparse_len(3, &mut shape);
+        unparse_len(4, &mut shape);
+        let n = shape.len();
+        assert_eq!(bs.len(), n + 12 * 8);
+        assert_eq!(bs[..n], shape[..]);
+        assert_eq!(bs[n..n + 8], 0.0f64.to_bytes()[..]);
+        assert_eq!(bs[n + 8..n + 16], 1.0f64.to_bytes()[..]);
+        assert_eq!(Array2::<f64>::from_bytes(&bs), Some(a.clone()));
+        assert_eq!(
+            Array2::<f64>::from_bytes(&a.t().to_owned().to_bytes()),
+            Some(a.t().to_owned())
+        );
+        assert!(Array2::<f64>::from_bytes(&bs[..bs.len() - 1]).is_none());
+        let v: Array1<u16> = Array1::from(vec![1, 2, 3]);
+        assert_eq!(Array1::from_bytes(&v.to_bytes()), Some(v));
+        let d = Array::from_shape_vec(IxDyn(&[2, 1, 2]), vec![1u8, 2, 3, 4]).unwrap();
+        assert_eq!(Array::from_bytes(&d.to_bytes()), Some(d));
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(derive::Binary, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum SerdeShape {
+        Empty,
+        Circle(f64),
+        Segment(i32, i32),
+        Labeled { name: String, sides: Option<u8> },
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(derive::Binary, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct SerdeRecord {
+        id: u128,
+        delta: i64,
+        initial: char,
+        pair: (u8, bool),
+        tags: Vec<String>,
+        counts: BTreeMap<u16, u32>,
+        shapes: Vec<SerdeShape>,
+        unit: (),
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use super::{from_bytes_serde, to_bytes_serde};
+        let record = SerdeRecord {
+            id: u128::MAX - 3,
+            delta: -12,
+            initial: 'λ',
+            pair: (9, true),
+            tags: vec!["a".to_string(), "bc".to_string()],
+            counts: BTreeMap::from([(1, 10), (2, 20)]),
+            shapes: vec![
+                SerdeShape::Empty,
+                SerdeShape::Circle(1.5),
+                SerdeShape::Segment(-1, 1),
+                SerdeShape::Labeled {
+                    name: "square".to_string(),
+                    sides: Some(4),
+                },
+            ],
+            unit: (),
+        };
+        let bs = record.to_bytes();
+        assert_eq!(to_bytes_serde(&record), bs);
+        assert_eq!(from_bytes_serde::<SerdeRecord>(&bs), Some(record));
+        assert!(from_bytes_serde::<SerdeRecord>(&bs[..bs.len() - 1]).is_none());
+        let mut trailing = bs.clone();
+        trailing.push(0);
+        assert!(from_bytes_serde::<SerdeRecord>(&trailing).is_none());
+        assert!(from_bytes_serde::<SerdeShape>(&[4]).is_none());
+        assert!(from_bytes_serde::<Vec<()>>(&[0xff; 8]).is_none());
+        assert!(from_bytes_serde::<BTreeMap<(), ()>>(&[0xff; 8]).is_none());
+
+        // recursive types can't nest deeply enough to exhaust the stack
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Chain(Option<Box<Chain>>);
+        let mut shallow = vec![1; 10];
+        shallow.push(0);
+        assert!(from_bytes_serde::<Chain>(&shallow).is_some());
+        let mut deep = vec![1; 1_000_000];
+        deep.push(0);
+        assert!(from_bytes_serde::<Chain>(&deep).is_none());
+
+        // sequences of unknown length are counted before they are written
+        struct Evens(Vec<u32>);
+        impl serde::Serialize for Evens {
+            fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                s.collect_seq(self.0.iter().filter(|n| *n % 2 == 0))
+            }
+        }
+        assert_eq!(
+            to_bytes_serde(&Evens(vec![1, 2, 3, 4])),
+            vec![2u32, 4].to_bytes()
+        );
+    }
+
+    #[cfg(feature = "num-bigint")]
+    #[test]
+    fn test_bigint() {
+        use num_bigint::{BigInt, BigUint};
+        let large = BigUint::from(u128::MAX) * BigUint::from(u128::MAX) + 12345u32;
+        for n in [BigUint::ZERO, BigUint::from(255u8), large.clone()] {
+            let bs = n.to_bytes();
+            assert_eq!(bs, n.to_bytes_le().to_bytes());
+            assert_eq!(BigUint::from_bytes(&bs), Some(n));
+        }
+        assert_eq!(BigUint::ZERO.to_bytes(), vec![0u8].to_bytes());
+        for n in [
+            BigInt::ZERO,
+            BigInt::from(-1),
+            BigInt::from(i64::MIN),
+            BigInt::from(large.clone()),
+            -BigInt::from(large),
+        ] {
+            assert_eq!(BigInt::from_bytes(&n.to_bytes()), Some(n));
+        }
+        // a redundant most significant zero
+        assert!(BigUint::from_bytes(&vec![1u8, 0].to_bytes()).is_none());
+        assert!(BigUint::from_bytes(&Vec::<u8>::new().to_bytes()).is_none());
+        // negative zero
+        let mut negative_zero = vec![1];
+        negative_zero.extend_from_slice(&BigUint::ZERO.to_bytes());
+        assert!(BigInt::from_bytes(&negative_zero).is_none());
+    }
+
+    #[cfg(feature = "widestring")]
+    #[test]
+    fn test_widestring() {
+        use widestring::{U16CString, U16String};
+        for s in ["", "plain", "caf\u{e9}", "emoji \u{1F600}!"] {
+            let w = U16String::from_str(s);
+            assert_eq!(w, U16String::from_bytes(&w.to_bytes()).unwrap());
+            assert_eq!(w.to_bytes(), w.as_slice().to_vec().to_bytes());
+            let c = U16CString::from_str(s).unwrap();
+            assert_eq!(c, U16CString::from_bytes(&c.to_bytes()).unwrap());
+            assert_eq!(c.to_bytes(), w.to_bytes());
+        }
+        let emoji = U16String::from_str("\u{1F600}");
+        assert_eq!(emoji.len(), 2);
+        assert_eq!(emoji.to_bytes(), vec![0xD83Du16, 0xDE00].to_bytes());
+        // a lone surrogate isn't valid UTF-16, but is still a valid U16String
+        let lone = U16String::from_vec(vec![0xD800u16]);
+        assert_eq!(lone, U16String::from_bytes(&lone.to_bytes()).unwrap());
+        let interior_nul = U16String::from_vec(vec![b'a' as u16, 0, b'b' as u16]);
+        assert!(U16CString::from_bytes(&interior_nul.to_bytes()).is_none());
+    }
+
+    // Derived impls need nothing in scope besides `Binary`, as in a crate importing only
+    // `use binary::{derive::Binary, Binary};`.
+    mod minimal_imports {
+        use crate::Binary;
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        struct Plain {
+            a: u32,
+            b: String,
+        }
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        enum Open {
+            Known(u8),
+            #[binary(unknown)]
+            Other(u8, Vec<u8>),
+        }
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        #[binary(reserved = 2..4)]
+        enum Reserving {
+            A,
+            B(u8),
+        }
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        #[binary(fixed_size)]
+        struct Fixed {
+            a: u16,
+            b: [u8; 3],
+        }
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        #[binary(both_endian)]
+        struct Endian<T> {
+            a: T,
+            b: u16,
+        }
+
+        #[derive(crate::derive::Binary, Debug, PartialEq)]
+        #[binary(borrow)]
+        struct Borrowing<'a> {
+            name: &'a str,
+        }
+
+        #[test]
+        fn test_minimal_imports() {
+            let plain = Plain {
+                a: 1,
+                b: "b".to_string(),
+            };
+            assert_eq!(Plain::from_bytes(&plain.to_bytes()), Some(plain));
+            let other = Open::Other(9, vec![1, 2]);
+            assert_eq!(Open::from_bytes(&other.to_bytes()), Some(other));
+            assert_eq!(Open::from_bytes(&[0, 4]), Some(Open::Known(4)));
+            assert_eq!(
+                Reserving::try_parse(&[1, 5]),
+                Ok((Reserving::B(5), &[][..]))
+            );
+            assert!(Reserving::try_parse(&[3]).is_err());
+            assert_eq!(Reserving::from_bytes(&[0]), Some(Reserving::A));
+            assert_eq!(<Fixed as crate::FixedSize>::SIZE, 5);
+            let sized = Fixed { a: 1, b: [2; 3] };
+            assert_eq!(Fixed::from_bytes(&sized.to_bytes()), Some(sized));
+            let endian = Endian { a: 1u32, b: 2 };
+            let mut bs = Vec::new();
+            endian.unparse_be(&mut bs);
+            assert_eq!(bs, [0, 0, 0, 1, 0, 2]);
+            assert_eq!(Endian::parse_be(&bs), Some((endian, &[][..])));
+            let borrowing = Borrowing { name: "name" };
+            let bs = crate::Encode::to_bytes(&borrowing);
+            assert_eq!(
+                crate::BinaryBorrow::from_bytes_borrowed(&bs),
+                Some(borrowing)
+            );
+        }
+    }
 }